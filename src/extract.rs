@@ -1,30 +1,231 @@
 extern crate zip;
 
-use ::std::path::PathBuf;
+use ::hash;
+use ::std::collections::BTreeMap;
+use ::std::path::{Component, Path, PathBuf};
 use ::std::fs;
 use ::std::io;
+use ::std::process;
+use ::std::sync::atomic::{AtomicUsize, Ordering};
 
-pub fn extract_zip(path: PathBuf, dest: &PathBuf) {
-    let file = fs::File::open(&path).unwrap();
-    let mut archive = zip::ZipArchive::new(file).unwrap();
+// created next to the addon directory, so folders can be moved between
+// them with renames instead of copies
+const STAGING_DIR: &'static str = ".wam-staging";
+
+// keeps the staging directories of installs that run in parallel apart
+static STAGING_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// What extracting an addon put into the addon directory.
+#[derive(Debug, Clone, Default)]
+pub struct Extracted {
+    // top-level folders that were created
+    pub folders: Vec<String>,
+    // sha1 of every extracted file, keyed by its path relative to the addon
+    // directory with forward slashes, e.g. `MyAddon/MyAddon.toc`
+    pub files: BTreeMap<String, String>,
+}
+
+/// Installs a downloaded addon into the addon directory `dest` without ever
+/// leaving it half installed. The download is extracted into a staging
+/// directory first and only moved into place if every top-level folder has a
+/// toc. The folders in `replaced`, usually the ones of the installed version,
+/// and the ones the download overwrites are moved aside until the new ones
+/// are in place, and are restored if that fails.
+pub fn install(path: PathBuf, dest: &Path, replaced: &[String]) -> io::Result<Extracted> {
+    let staging_root = dest.parent().unwrap_or(dest).join(STAGING_DIR);
+    let staging = staging_root.join(format!(
+        "{}-{}", process::id(), STAGING_ID.fetch_add(1, Ordering::SeqCst)
+    ));
+
+    let (new_dir, old_dir) = (staging.join("new"), staging.join("old"));
+    fs::create_dir_all(&new_dir)?;
+    fs::create_dir_all(&old_dir)?;
+
+    let result = extract(path, &new_dir)
+        .and_then(|extracted| {
+            validate(&new_dir, &extracted)?;
+            swap(&new_dir, &old_dir, dest, replaced, &extracted.folders)?;
+            Ok(extracted)
+        })
+        .map(|mut extracted| {
+            // files at the root of the archive stay behind in staging
+            let folders = extracted.folders.clone();
+            extracted.files.retain(|file, _| folders.iter()
+                .any(|it| file.starts_with(&format!("{}/", it))));
+
+            extracted
+        });
+
+    let _ = fs::remove_dir_all(&staging);
+    // only succeeds once no other install is using it anymore
+    let _ = fs::remove_dir(&staging_root);
+
+    result
+}
+
+/// Checks that an extracted addon contains at least one folder, and that
+/// every folder has a toc the client could load it with.
+fn validate(dir: &Path, extracted: &Extracted) -> io::Result<()> {
+    if extracted.folders.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "download contains no addon folders"));
+    }
+
+    for folder in &extracted.folders {
+        let has_toc = fs::read_dir(dir.join(folder))?
+            .filter_map(|it| it.ok())
+            .any(|it| it.path().extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase() == "toc")
+                .unwrap_or(false));
+
+        if !has_toc {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, format!("{} contains no toc file", folder)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the `folders` from `new_dir` into `dest`, after moving the folders
+/// they replace from `dest` into `old_dir`. If any of the renames fails, the
+/// folders that were already moved are put back where they were.
+fn swap(
+    new_dir: &Path, old_dir: &Path, dest: &Path, replaced: &[String], folders: &[String]
+) -> io::Result<()> {
+    let mut moved_out = Vec::new();
+    let mut moved_in = Vec::new();
+
+    let result = move_folders(new_dir, old_dir, dest, replaced, folders, &mut moved_out, &mut moved_in);
+
+    if result.is_err() {
+        for folder in &moved_in {
+            let _ = fs::remove_dir_all(dest.join(folder));
+        }
+
+        for folder in &moved_out {
+            let _ = fs::rename(old_dir.join(folder), dest.join(folder));
+        }
+    }
+
+    result
+}
+
+fn move_folders(
+    new_dir: &Path, old_dir: &Path, dest: &Path, replaced: &[String], folders: &[String],
+    moved_out: &mut Vec<String>, moved_in: &mut Vec<String>
+) -> io::Result<()> {
+    for folder in replaced.iter().chain(folders.iter()) {
+        if moved_out.contains(folder) || !dest.join(folder).exists() {
+            continue;
+        }
+
+        fs::rename(dest.join(folder), old_dir.join(folder))?;
+        moved_out.push(folder.clone());
+    }
+
+    for folder in folders {
+        fs::rename(new_dir.join(folder), dest.join(folder))?;
+        moved_in.push(folder.clone());
+    }
+
+    Ok(())
+}
+
+/// Extracts a downloaded addon into `dest`. Downloads are usually zips, but
+/// providers that build addons from source hand over a directory instead.
+pub fn extract(path: PathBuf, dest: &Path) -> io::Result<Extracted> {
+    if path.is_dir() {
+        extract_dir(path, dest)
+    } else {
+        extract_zip(path, dest)
+    }
+}
+
+/// Copies every folder inside of `path` into `dest`.
+pub fn extract_dir(path: PathBuf, dest: &Path) -> io::Result<Extracted> {
+    let mut extracted = Extracted::default();
+
+    for entry in fs::read_dir(&path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let name = entry.file_name().to_string_lossy().into_owned();
+        copy_dir(&entry.path(), &dest.join(&name))?;
+
+        for (file, hash) in hash::hash_files(&entry.path())? {
+            extracted.files.insert(format!("{}/{}", name, file), hash);
+        }
+
+        extracted.folders.push(name);
+    }
+
+    extracted.folders.sort();
+    Ok(extracted)
+}
+
+/// Recursively copies the contents of `src` into `dest`.
+pub fn copy_dir(src: &Path, dest: &Path) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts the zip at `path` into `dest`, hashing every file on the way.
+pub fn extract_zip(path: PathBuf, dest: &Path) -> io::Result<Extracted> {
+    let file = fs::File::open(&path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut extracted = Extracted::default();
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
+        let mut file = archive.by_index(i)?;
         let outpath = file.sanitized_name();
+
+        // files at the root of the archive don't belong to any addon folder,
+        // so only entries that are nested in (or are) a directory count
+        let is_dir = (&*file.name()).ends_with('/');
+        if is_dir || outpath.components().count() > 1 {
+            if let Some(Component::Normal(top)) = outpath.components().next() {
+                let top = top.to_string_lossy().into_owned();
+                if !extracted.folders.contains(&top) {
+                    extracted.folders.push(top);
+                }
+            }
+        }
+
+        let relative = outpath.components()
+            .map(|it| it.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<String>>()
+            .join("/");
+
         let outpath = dest.join(outpath);
 
         if (&*file.name()).ends_with('/') {
             // println!("File {} extracted to \"{}\"", i, outpath.as_path().display());
-            fs::create_dir_all(&outpath).unwrap();
+            fs::create_dir_all(&outpath)?;
         } else {
             // println!("File {} extracted to \"{}\" ({} bytes)", i, outpath.as_path().display(), file.size());
             if let Some(p) = outpath.parent() {
                 if !p.exists() {
-                    fs::create_dir_all(&p).unwrap();
+                    fs::create_dir_all(&p)?;
                 }
             }
-            let mut outfile = fs::File::create(&outpath).unwrap();
-            io::copy(&mut file, &mut outfile).unwrap();
+            let mut outfile = fs::File::create(&outpath)?;
+            let hash = hash::copy_hashed(&mut file, &mut outfile)?;
+            extracted.files.insert(relative, hash);
         }
 
         // Get and Set permissions
@@ -33,8 +234,106 @@ pub fn extract_zip(path: PathBuf, dest: &PathBuf) {
             use std::os::unix::fs::PermissionsExt;
 
             if let Some(mode) = file.unix_mode() {
-                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode)).unwrap();
+                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
             }
         }
     }
+
+    extracted.folders.sort();
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::std::env;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wam-extract-test-{}-{}", process::id(), name));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn addon_folder(dir: &Path, name: &str, toc: Option<&str>) {
+        fs::create_dir_all(dir.join(name)).unwrap();
+        fs::write(dir.join(name).join(format!("{}.lua", name)), "").unwrap();
+        if let Some(contents) = toc {
+            fs::write(dir.join(name).join(format!("{}.toc", name)), contents).unwrap();
+        }
+    }
+
+    fn read_toc(dir: &Path, name: &str) -> String {
+        fs::read_to_string(dir.join(name).join(format!("{}.toc", name))).unwrap()
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir).unwrap()
+            .map(|it| it.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn failed_validation_leaves_dest_untouched() {
+        let dir = test_dir("validation");
+        let (download, dest) = (dir.join("download"), dir.join("AddOns"));
+        addon_folder(&download, "MyAddon", None);
+        addon_folder(&dest, "MyAddon", Some("old"));
+
+        let result = install(download, &dest, &[String::from("MyAddon")]);
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(entries(&dest), vec!["MyAddon"]);
+        assert_eq!(read_toc(&dest, "MyAddon"), "old");
+        assert!(!dir.join(STAGING_DIR).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_rename_restores_moved_folders() {
+        let dir = test_dir("rollback");
+        let (new_dir, old_dir, dest) = (dir.join("new"), dir.join("old"), dir.join("AddOns"));
+        fs::create_dir_all(&old_dir).unwrap();
+        addon_folder(&new_dir, "MyAddon", Some("new"));
+        addon_folder(&dest, "MyAddon", Some("old"));
+        addon_folder(&dest, "MyAddon_Options", Some("old"));
+
+        // MyAddon_Options is moved out, but missing from the new version
+        let folders = vec![String::from("MyAddon"), String::from("MyAddon_Options")];
+        let result = swap(&new_dir, &old_dir, &dest, &folders, &folders);
+
+        assert!(result.is_err());
+        assert_eq!(entries(&dest), vec!["MyAddon", "MyAddon_Options"]);
+        assert_eq!(read_toc(&dest, "MyAddon"), "old");
+        assert_eq!(read_toc(&dest, "MyAddon_Options"), "old");
+        assert!(entries(&old_dir).is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn removes_replaced_folders_that_are_no_longer_shipped() {
+        let dir = test_dir("replaced");
+        let (download, dest) = (dir.join("download"), dir.join("AddOns"));
+        addon_folder(&download, "MyAddon", Some("new"));
+        addon_folder(&dest, "MyAddon", Some("old"));
+        addon_folder(&dest, "MyAddon_Legacy", Some("old"));
+        addon_folder(&dest, "OtherAddon", Some("other"));
+
+        let replaced = vec![String::from("MyAddon"), String::from("MyAddon_Legacy")];
+        let extracted = install(download, &dest, &replaced).unwrap();
+
+        assert_eq!(extracted.folders, vec!["MyAddon"]);
+        assert_eq!(extracted.files.keys().collect::<Vec<&String>>(), vec!["MyAddon/MyAddon.lua", "MyAddon/MyAddon.toc"]);
+        assert_eq!(entries(&dest), vec!["MyAddon", "OtherAddon"]);
+        assert_eq!(read_toc(&dest, "MyAddon"), "new");
+        assert_eq!(read_toc(&dest, "OtherAddon"), "other");
+        assert!(!dir.join(STAGING_DIR).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,29 @@
+/// Matches `text` against a simple wildcard pattern, where `*` matches any
+/// number of characters and `?` matches exactly one. Matching is case
+/// insensitive, since it is used for file and addon names.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<char>>();
+    let text = text.to_lowercase().chars().collect::<Vec<char>>();
+
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` in the pattern and where it started matching
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&it| it == '*')
+}
@@ -0,0 +1,98 @@
+extern crate sha1;
+
+use ::std::collections::BTreeMap;
+use ::std::fs::{self, File};
+use ::std::io::{self, Read, Write};
+use ::std::path::Path;
+
+/// Returns the hex encoded sha1 of a file's contents.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = sha1::Sha1::new();
+    hash_file_into(path, &mut hasher)?;
+
+    Ok(hasher.digest().to_string())
+}
+
+/// Returns a hash over all files in a directory, including their paths
+/// relative to it, so renames change the hash just like edits do.
+pub fn hash_dir(path: &Path) -> io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(path, path, &mut files)?;
+    files.sort();
+
+    let mut hasher = sha1::Sha1::new();
+    for relative in files {
+        hasher.update(relative.as_bytes());
+        hash_file_into(&path.join(&relative), &mut hasher)?;
+    }
+
+    Ok(hasher.digest().to_string())
+}
+
+/// Returns the hex encoded sha1 of every file in a directory, keyed by their
+/// paths relative to it.
+pub fn hash_files(path: &Path) -> io::Result<BTreeMap<String, String>> {
+    let mut files = Vec::new();
+    collect_files(path, path, &mut files)?;
+
+    let mut hashes = BTreeMap::new();
+    for relative in files {
+        let hash = hash_file(&path.join(&relative))?;
+        hashes.insert(relative, hash);
+    }
+
+    Ok(hashes)
+}
+
+/// Copies everything from `reader` to `writer` and returns the hex encoded
+/// sha1 of it, so files don't have to be read again after writing them.
+pub fn copy_hashed<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<String> {
+    let mut hasher = sha1::Sha1::new();
+    let mut buf = [0; 8192];
+
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            return Ok(hasher.digest().to_string());
+        }
+
+        hasher.update(&buf[..read]);
+        writer.write_all(&buf[..read])?;
+    }
+}
+
+fn hash_file_into(path: &Path, hasher: &mut sha1::Sha1) -> io::Result<()> {
+    let mut f = File::open(path)?;
+    let mut buf = [0; 8192];
+
+    loop {
+        let read = f.read(&mut buf)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        hasher.update(&buf[..read]);
+    }
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            // always use forward slashes so hashes match across platforms
+            let relative = relative.components()
+                .map(|it| it.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<String>>()
+                .join("/");
+
+            files.push(relative);
+        }
+    }
+
+    Ok(())
+}
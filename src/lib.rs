@@ -0,0 +1,272 @@
+//! Core of the WoW addon manager: the addon and lock types, providers that
+//! addons can be installed from, and extraction of downloaded archives.
+//!
+//! Additional providers can be plugged in by implementing
+//! `providers::Provider` and registering it with a `providers::Registry`.
+
+#[macro_use]
+extern crate serde_derive;
+
+extern crate reqwest;
+extern crate serde;
+
+#[macro_use]
+extern crate futures;
+
+#[cfg(test)]
+extern crate serde_json;
+
+pub mod extract;
+pub mod glob;
+pub mod hash;
+pub mod providers;
+pub mod toc;
+
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Addon {
+    pub name: String,
+    pub provider: String,
+    // wildcard pattern for picking the right file from releases that
+    // have multiple assets, e.g. `*-classic.zip`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    // source location for providers that don't resolve addons by name,
+    // e.g. the repository for git addons
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    // subdirectory of the source that contains the addon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    // least stable kind of release to install, falls back to the global
+    // default and then to `release`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+    // exact version to install instead of the latest one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    // keeps the addon at the version in the lock file once it's installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<bool>,
+    // game flavor to pick builds for, defaults to the flavor of the target
+    // the addon is installed into
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flavor: Option<Flavor>,
+}
+
+impl Addon {
+    pub fn new(provider: &str, name: &str) -> Addon {
+        Addon {
+            provider: String::from(provider),
+            name: String::from(name),
+            ..Default::default()
+        }
+    }
+
+    /// Checks whether the addon is held at the version in `lock`, either
+    /// because `lock` is the exact version it wants or because it's pinned.
+    /// An exact version takes precedence, so changing it still updates.
+    pub fn is_pinned_to(&self, lock: &AddonLock) -> bool {
+        match self.version {
+            Some(ref version) => version == &lock.version,
+            _ => self.pin.unwrap_or(false),
+        }
+    }
+
+    /// Checks whether `lock` should be installed over the `installed` one.
+    /// Addons with an exact version are switched to it even if it's older.
+    pub fn should_update(&self, lock: &AddonLock, installed: &AddonLock) -> bool {
+        match self.version {
+            Some(_) => lock.resolved != installed.resolved || lock.version != installed.version,
+            _ => lock.is_newer_than(installed),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct AddonLock {
+    pub name: String,
+    pub resolved: String,
+    // do i even need this? timestamp is always better for comparing
+    // keeping it for now for displaying information about installed addons
+    pub version: String,
+    pub timestamp: u64,
+    // where the locked version is downloaded from, for providers that
+    // resolve a direct link or source location
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    // hash of the downloaded content, for providers that can't tell when
+    // their source changed. takes precedence over the timestamp if set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    // channel the addon was resolved from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<Channel>,
+    // when this version was installed, as opposed to when it was released
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed: Option<u64>,
+    // top-level folders in the addon directory that were created when
+    // extracting this addon, used to cleanly uninstall it again
+    #[serde(default)]
+    pub folders: Vec<String>,
+    // set for addons that were installed as a dependency of others instead
+    // of because they're in the config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dependency: Option<bool>,
+    // addons that currently require this one. once this is empty for a
+    // dependency, nothing needs it anymore
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_by: Vec<String>,
+    // sha1 of every file that was extracted, keyed by its path in the addon
+    // directory. kept last, since toml needs tables after plain values
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+}
+
+/// Release channels, ordered from most to least stable. Selecting a channel
+/// allows releases from that channel and all more stable ones.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Release,
+    Beta,
+    Alpha,
+}
+
+impl Channel {
+    /// Checks whether a release from `channel` may be installed when this
+    /// channel is selected.
+    pub fn allows(&self, channel: Channel) -> bool {
+        channel <= *self
+    }
+}
+
+impl Default for Channel {
+    fn default() -> Channel {
+        Channel::Release
+    }
+}
+
+/// Game flavors that have their own installation folder, and usually their
+/// own builds of an addon.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Flavor {
+    Retail,
+    Classic,
+    ClassicEra,
+    Ptr,
+    Beta,
+}
+
+impl Flavor {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Flavor::Retail => "retail",
+            Flavor::Classic => "classic",
+            Flavor::ClassicEra => "classic_era",
+            Flavor::Ptr => "ptr",
+            Flavor::Beta => "beta",
+        }
+    }
+
+    /// Name of the folder inside the WoW installation that the flavor is
+    /// installed in, e.g. `_retail_`.
+    pub fn dir_name(&self) -> &'static str {
+        match *self {
+            Flavor::Retail => "_retail_",
+            Flavor::Classic => "_classic_",
+            Flavor::ClassicEra => "_classic_era_",
+            Flavor::Ptr => "_ptr_",
+            Flavor::Beta => "_beta_",
+        }
+    }
+
+    /// Product code of the flavor's client in the installation's
+    /// `.build.info`.
+    pub fn product(&self) -> &'static str {
+        match *self {
+            Flavor::Retail => "wow",
+            Flavor::Classic => "wow_classic",
+            Flavor::ClassicEra => "wow_classic_era",
+            Flavor::Ptr => "wowt",
+            Flavor::Beta => "wow_beta",
+        }
+    }
+
+    /// The flavor whose addon builds this one uses. The test clients run
+    /// retail builds, since addons don't publish separate ones for them.
+    pub fn base(&self) -> Flavor {
+        match *self {
+            Flavor::Ptr | Flavor::Beta => Flavor::Retail,
+            flavor => flavor,
+        }
+    }
+
+    /// Guesses which flavor a file is built for from the suffixes that addon
+    /// packagers add to its name, e.g. `MyAddon-1.0-classic.zip`. Returns
+    /// `None` for files without any, which usually work for retail.
+    pub fn from_file_name(name: &str) -> Option<Flavor> {
+        let name = name.to_lowercase();
+        let words = name.split(|c: char| !c.is_alphanumeric()).collect::<Vec<&str>>();
+        let has_any = |tokens: &[&str]| words.iter().any(|it| tokens.contains(it));
+
+        if has_any(&["bcc", "tbc", "wrath", "wotlk", "wotlkc", "cata", "mop"]) {
+            Some(Flavor::Classic)
+        } else if has_any(&["classic", "vanilla", "era"]) {
+            Some(Flavor::ClassicEra)
+        } else if has_any(&["mainline", "retail"]) {
+            Some(Flavor::Retail)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Flavor {
+    fn default() -> Flavor {
+        Flavor::Retail
+    }
+}
+
+impl AddonLock {
+    /// Checks whether the addon was only installed because others need it.
+    pub fn is_dependency(&self) -> bool {
+        // older locks only recorded which addons required the dependency
+        self.dependency.unwrap_or(false) || !self.required_by.is_empty()
+    }
+
+    /// Checks whether this lock describes a newer version than `other`.
+    /// Locks with content hashes are compared by hash instead of timestamp.
+    pub fn is_newer_than(&self, other: &AddonLock) -> bool {
+        match (&self.hash, &other.hash) {
+            (&Some(ref hash), &Some(ref other_hash)) => hash != other_hash,
+            _ => self.timestamp > other.timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_flavor_from_file_name() {
+        assert_eq!(Flavor::from_file_name("MyAddon-1.0-classic.zip"), Some(Flavor::ClassicEra));
+        assert_eq!(Flavor::from_file_name("MyAddon-1.0-Vanilla.zip"), Some(Flavor::ClassicEra));
+        assert_eq!(Flavor::from_file_name("MyAddon_1.0_bcc.zip"), Some(Flavor::Classic));
+        assert_eq!(Flavor::from_file_name("MyAddon-1.0-cata.zip"), Some(Flavor::Classic));
+        assert_eq!(Flavor::from_file_name("MyAddon-1.0-mainline.zip"), Some(Flavor::Retail));
+        assert_eq!(Flavor::from_file_name("MyAddon-1.0.zip"), None);
+        // only whole words count
+        assert_eq!(Flavor::from_file_name("Classicish-1.0.zip"), None);
+        assert_eq!(Flavor::from_file_name("Catalog-1.0.zip"), None);
+    }
+}
@@ -4,90 +4,317 @@ extern crate serde_derive;
 #[macro_use]
 extern crate lazy_static;
 
-extern crate reqwest;
 extern crate serde;
 extern crate toml;
+extern crate chrono;
 
-#[macro_use]
 extern crate futures;
 extern crate tokio;
 
 extern crate clap;
-use clap::{App, AppSettings, SubCommand};
+use clap::{App, AppSettings, Arg, SubCommand};
 
-mod extract;
-mod providers;
+extern crate wam;
+use wam::{extract, glob, hash, providers, toc, Addon, AddonLock, Channel, Flavor};
 
+use std::collections::BTreeMap;
+use std::env;
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use std::io::prelude::*;
 use std::error::Error;
+use std::cmp::Reverse;
+use std::process;
 
 use futures::{Future, Stream};
+use futures::future::Either;
+use chrono::prelude::*;
 
 const TEMP_DIR: &'static str = ".wam-temp";
-const ADDON_DIR_PATH: &'static str = "Interface/Addons";
+
+// the addon folder is usually spelled `AddOns`, but older installations
+// and case sensitive file systems can have it as `Addons`
+const ADDON_DIR_PATHS: [&'static str; 2] = ["Interface/AddOns", "Interface/Addons"];
+
+// overrides `wow_path` from the config, also set by `--wow-path`
+const WOW_PATH_ENV: &'static str = "WAM_WOW_PATH";
 
 const CONFIG_FILE_PATH: &'static str = "wam.toml";
 const LOCK_FILE_PATH: &'static str = "wam-lock.toml";
 
+// only this many search results are shown, since each one that is missing
+// version information needs an additional request to fill it in
+const MAX_SEARCH_RESULTS: usize = 20;
+
+// exit codes of `outdated` and `check`, so scripts can tell finding
+// something apart from failing to look. `install` also fails with the latter
+const EXIT_FOUND: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+
 #[derive(Serialize, Deserialize, Debug)]
 struct ConfigFile {
     pub config: Option<GlobalConfig>,
+    // with targets, these are installed into every one of them. skipped when
+    // empty, since toml can't have an empty array after the `[config]` table
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub addons: Vec<Addon>,
+    // tocs only name the folders an addon requires, so this maps them to
+    // the addons that provide them, e.g. `Ace3 = "curse/ace3"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+    // separate installations managed from the same config, e.g. one for
+    // retail and one for classic. without any, the installation from the
+    // global config is the only one
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, TargetConfig>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 struct GlobalConfig {
     pub parallel: Option<usize>,
+    // default release channel for addons that don't set their own
+    pub channel: Option<Channel>,
+    // WoW installation to manage, defaults to the current directory
+    pub wow_path: Option<String>,
+    pub flavor: Option<Flavor>,
+    // interface version of the client, e.g. 110002. read from the
+    // installation's `.build.info` if it's not set
+    pub interface: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct TargetConfig {
+    // these fall back to the global config if they're not set
+    pub wow_path: Option<String>,
+    pub flavor: Option<Flavor>,
+    pub interface: Option<u32>,
+    // addons only installed into this target, in addition to the shared ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addons: Vec<Addon>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Addon {
-    pub name: String,
-    pub provider: String,
+impl GlobalConfig {
+    /// Fills in settings the addon doesn't specify itself with the global
+    /// defaults. Only used for resolving, so the defaults never end up in
+    /// the addon entries when the config is saved.
+    fn apply_defaults(&self, mut addon: Addon) -> Addon {
+        addon.channel = addon.channel.or(self.channel);
+        addon
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 struct LockFile {
+    // locks for the installation from the global config
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub addons: Vec<AddonLock>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, TargetLock>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
-pub struct AddonLock {
-    pub name: String,
-    pub resolved: String,
-    // do i even need this? timestamp is always better for comparing
-    // keeping it for now for displaying information about installed addons
-    pub version: String,
-    pub timestamp: u64,
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct TargetLock {
+    #[serde(default)]
+    pub addons: Vec<AddonLock>,
 }
 
-lazy_static! {
-    static ref LOCK: LockFile = {
-        let lock_path = Path::new(LOCK_FILE_PATH);
+impl LockFile {
+    fn addons_for(&self, target: &Option<String>) -> Vec<AddonLock> {
+        match *target {
+            Some(ref name) => self.targets.get(name)
+                .map(|it| it.addons.clone())
+                .unwrap_or(Vec::new()),
+            _ => self.addons.clone(),
+        }
+    }
 
-        if !lock_path.exists() || !lock_path.is_file() {
-            LockFile { addons: Vec::new() }
-        } else {
-            let mut contents = String::new();
-            let mut f = File::open(lock_path).unwrap();
-            f.read_to_string(&mut contents).unwrap();
-            toml::from_str::<LockFile>(&contents)
-                .expect("failed to parse lock file")
+    fn addons_for_mut(&mut self, target: &Option<String>) -> &mut Vec<AddonLock> {
+        match *target {
+            Some(ref name) => &mut self.targets.entry(name.clone())
+                .or_insert_with(Default::default)
+                .addons,
+            _ => &mut self.addons,
         }
-    };
+    }
 }
 
-lazy_static! {
-    static ref ADDON_DIR: PathBuf = {
-        let addon_dir = Path::new(ADDON_DIR_PATH);
-        if !addon_dir.is_dir() {
-            fs::create_dir_all(addon_dir).unwrap();
+/// A WoW installation that addons are installed into, together with its
+/// addons from the config and their locks.
+#[derive(Debug, Clone)]
+struct Target {
+    // `None` for the installation from the global config
+    name: Option<String>,
+    flavor: Flavor,
+    // interface version of the client, `None` if it's unknown
+    interface: Option<u32>,
+    addon_dir: PathBuf,
+    addons: Vec<Addon>,
+    locks: Vec<AddonLock>,
+}
+
+impl Target {
+    fn find_lock(&self, addon: &Addon) -> Option<AddonLock> {
+        self.locks.iter().find(|it| {
+            it.name == format!("{}/{}", addon.provider, addon.name)
+        }).map(Clone::clone)
+    }
+
+    fn has_missing_folders(&self, lock: &AddonLock) -> bool {
+        lock.folders.is_empty() || lock.folders.iter().any(|it| !self.addon_dir.join(it).is_dir())
+    }
+
+    /// Checks whether the folders of an installed addon are all there and
+    /// still contain exactly the files it was installed with.
+    fn is_intact(&self, lock: &AddonLock) -> bool {
+        !self.has_missing_folders(lock) && lock.files.iter().all(|(file, hash)| {
+            hash::hash_file(&self.addon_dir.join(file))
+                .map(|it| &it == hash)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Checks whether the addon directory has a folder, ignoring case like
+    /// the client does.
+    fn has_folder(&self, folder: &str) -> bool {
+        fs::read_dir(&self.addon_dir)
+            .map(|entries| entries
+                .filter_map(|it| it.ok())
+                .any(|it| it.path().is_dir() && it.file_name().to_string_lossy().eq_ignore_ascii_case(folder)))
+            .unwrap_or(false)
+    }
+
+    fn is_configured(&self, name: &str) -> bool {
+        self.addons.iter().any(|it| format!("{}/{}", it.provider, it.name) == name)
+    }
+
+    /// The addons from the config together with the ones that are only
+    /// installed as their dependencies, which are kept up to date the same way.
+    fn all_addons(&self) -> Vec<Addon> {
+        let mut addons = self.addons.clone();
+        addons.extend(self.locks.iter()
+            .filter(|it| it.is_dependency() && !self.is_configured(&it.name))
+            .filter_map(|it| parse_name(&it.name)));
+
+        addons
+    }
+
+    /// Carries over whether an installed addon is a dependency, and which
+    /// addons require it, to the lock of its new version. Once it's in the
+    /// config, it's installed for itself.
+    fn keep_dependency(&self, lock: &mut AddonLock) {
+        if self.is_configured(&lock.name) {
+            return;
         }
 
-        addon_dir.to_path_buf()
-    };
+        if let Some(installed) = self.locks.iter().find(|it| it.name == lock.name) {
+            if installed.is_dependency() {
+                lock.dependency = Some(true);
+            }
+            lock.required_by = installed.required_by.clone();
+        }
+    }
+
+    /// Reads the folders that an installed addon requires from its tocs,
+    /// leaving out the ones it ships itself and the ones of the client.
+    fn required_folders(&self, lock: &AddonLock) -> Vec<String> {
+        let mut required: Vec<String> = Vec::new();
+
+        for folder in &lock.folders {
+            let toc = match toc::read(&self.addon_dir.join(folder), self.flavor) {
+                Some(toc) => toc,
+                _ => continue,
+            };
+
+            for dependency in toc.dependencies {
+                let is_known = lock.folders.iter()
+                    .chain(required.iter())
+                    .any(|it| it.eq_ignore_ascii_case(&dependency));
+
+                if !is_known && !dependency.to_lowercase().starts_with("blizzard_") {
+                    required.push(dependency);
+                }
+            }
+        }
+
+        required
+    }
+
+    /// Installs a downloaded addon into the addon directory and records what
+    /// was extracted in its lock. The folders of the installed version are
+    /// replaced as a whole, so files and folders the new version doesn't ship
+    /// anymore don't stay behind. If it fails, the installed version is left
+    /// as it was.
+    fn install_downloaded(&self, downloaded: PathBuf, mut lock: AddonLock) -> Result<AddonLock, String> {
+        // folders that another addon also extracted are kept
+        let replaced = self.locks.iter()
+            .find(|it| it.name == lock.name)
+            .map(|installed| installed.folders.iter()
+                .filter(|folder| !self.locks.iter()
+                    .any(|it| it.name != lock.name && it.folders.contains(folder)))
+                .cloned()
+                .collect())
+            .unwrap_or(Vec::new());
+
+        let extracted = extract::install(downloaded, &self.addon_dir, &replaced)
+            .map_err(|err| format!("could not install {}: {}", lock.name, err))?;
+
+        lock.folders = extracted.folders;
+        lock.files = extracted.files;
+        lock.installed = Some(now());
+
+        self.warn_outdated(&lock);
+        Ok(lock)
+    }
+
+    /// Returns the folders of an installed addon whose tocs are behind the
+    /// interface version of the client, along with their interface version.
+    fn outdated_folders(&self, lock: &AddonLock) -> Vec<(String, u32)> {
+        let current = match self.interface {
+            Some(current) => current,
+            _ => return Vec::new(),
+        };
+
+        lock.folders.iter()
+            .filter_map(|folder| toc::read(&self.addon_dir.join(folder), self.flavor)
+                .filter(|toc| toc.is_outdated_for(current))
+                .and_then(|toc| toc.max_interface())
+                .map(|interface| (folder.clone(), interface)))
+            .collect()
+    }
+
+    /// Prints a warning for every folder of an installed addon that the
+    /// client will only load with "Load out of date AddOns" enabled.
+    fn warn_outdated(&self, lock: &AddonLock) {
+        for (folder, interface) in self.outdated_folders(lock) {
+            println!(
+                "warning: {} of {} is made for interface {}, the client is on {}",
+                folder, lock.name, interface, self.interface.unwrap_or(0)
+            );
+        }
+    }
+
+    /// Fills in the global defaults and the flavor of the target for
+    /// settings the addon doesn't specify itself.
+    fn apply_defaults(&self, config: &GlobalConfig, addon: Addon) -> Addon {
+        let mut addon = config.apply_defaults(addon);
+        addon.flavor = addon.flavor.or(Some(self.flavor));
+        addon
+    }
+
+    /// Prints which target the following output belongs to. Nothing is
+    /// printed if there is only the installation from the global config.
+    fn print_heading(&self) {
+        if let Some(ref name) = self.name {
+            println!(
+                "{} ({}, {}):",
+                name, self.flavor.name(), self.addon_dir.display()
+            );
+        }
+    }
+}
+
+lazy_static! {
+    static ref PROVIDERS: providers::Registry = providers::Registry::with_builtin();
 }
 
 fn main() {
@@ -96,26 +323,69 @@ fn main() {
         .author("Hilmar Wiegand <me@hwgnd.de>")
         .about("WoW Addon Manager")
         .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(Arg::from_usage("--wow-path [PATH] 'path to the WoW installation, overrides the config'")
+            .global(true))
+        .arg(Arg::from_usage("--target [TARGET] 'only operate on this target instead of all of them'")
+            .global(true))
         .subcommands(vec![
             SubCommand::with_name("install")
-                .about("install new addons and update existing ones"),
+                .about("install new addons and update existing ones")
+                .args_from_usage(
+                    "--locked 'install exactly the versions in the lock file'
+                    [NAMES]... 'only install these addons, in format <provider>/<name> or as a wildcard pattern'"
+                ),
 
             SubCommand::with_name("add")
                 .about("add and install a new addon")
                 .args_from_usage("[NAME] 'addon name in format <provider>/<name>'"),
 
             SubCommand::with_name("remove")
-                .about("not implemented"),
+                .about("remove an addon and delete its folders")
+                .args_from_usage("<NAME> 'addon name in format <provider>/<name>'"),
+
+            SubCommand::with_name("prune")
+                .about("remove dependencies that no addon requires anymore")
+                .args_from_usage("--dry-run 'only list the addons that would be removed'"),
+
+            SubCommand::with_name("outdated")
+                .about("list addons that have updates available, exits with 1 if there are any and 2 on errors"),
+
+            SubCommand::with_name("check")
+                .about("list installed addons that are out of date for the client, exits with 1 if there are any and 2 on errors"),
+
+            SubCommand::with_name("list")
+                .about("list installed addons"),
+
+            SubCommand::with_name("info")
+                .about("show details about an installed addon")
+                .args_from_usage("<NAME> 'addon name in format <provider>/<name>'"),
 
             SubCommand::with_name("search")
-                .about("not implemented"),
+                .about("search all providers for addons")
+                .args_from_usage("<TERM> 'search term'"),
         ]);
 
     let matches = app.get_matches();
 
-    if let Some(_) = matches.subcommand_matches("install") {
-        match install() {
-            Err(err) => println!("an error occurred: {:?}", err),
+    // the override is passed on through the same variable that can be used
+    // to set it from outside, so it's picked up wherever targets are loaded
+    if let Some(path) = matches.value_of("wow-path") {
+        env::set_var(WOW_PATH_ENV, path);
+    }
+
+    let target = matches.value_of("target");
+    let mut exit_code = 0;
+
+    if let Some(matches) = matches.subcommand_matches("install") {
+        let names = matches.values_of("NAMES")
+            .map(|it| it.map(|name| name.to_lowercase()).collect())
+            .unwrap_or(Vec::new());
+
+        match install(matches.is_present("locked"), names, target) {
+            Err(err) => {
+                println!("an error occurred: {:?}", err);
+                exit_code = EXIT_ERROR;
+            },
             _ => println!("all done!"),
         };
     }
@@ -123,140 +393,1332 @@ fn main() {
     if let Some(matches) = matches.subcommand_matches("add") {
         let name = String::from(matches.value_of("NAME").unwrap());
 
-        match add(name) {
+        match add(name, target) {
             Err(err) => println!("add error occurred: {:?}", err),
             _ => println!("added!"),
         };
     }
 
+    if let Some(matches) = matches.subcommand_matches("remove") {
+        let name = String::from(matches.value_of("NAME").unwrap());
+
+        match remove(name, target) {
+            Err(err) => println!("remove error occurred: {:?}", err),
+            Ok(true) => println!("removed!"),
+            _ => (),
+        };
+    }
+
+    if let Some(matches) = matches.subcommand_matches("prune") {
+        match prune(matches.is_present("dry-run"), target) {
+            Err(err) => println!("prune error occurred: {:?}", err),
+            _ => println!("all done!"),
+        };
+    }
+
+    if let Some(_) = matches.subcommand_matches("outdated") {
+        match outdated(target) {
+            Err(err) => {
+                println!("an error occurred: {:?}", err);
+                exit_code = EXIT_ERROR;
+            },
+            Ok(true) => exit_code = EXIT_FOUND,
+            _ => (),
+        };
+    }
+
+    if let Some(_) = matches.subcommand_matches("check") {
+        match check(target) {
+            Err(err) => {
+                println!("check error occurred: {:?}", err);
+                exit_code = EXIT_ERROR;
+            },
+            Ok(true) => exit_code = EXIT_FOUND,
+            _ => (),
+        };
+    }
+
+    if let Some(_) = matches.subcommand_matches("list") {
+        if let Err(err) = list(target) {
+            println!("list error occurred: {:?}", err);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("info") {
+        let name = String::from(matches.value_of("NAME").unwrap());
+
+        if let Err(err) = info(name, target) {
+            println!("info error occurred: {:?}", err);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("search") {
+        let term = String::from(matches.value_of("TERM").unwrap());
+
+        if let Err(err) = search(term) {
+            println!("search error occurred: {:?}", err);
+        }
+    }
+
     delete_temp_dir().unwrap();
+    process::exit(exit_code);
 }
 
-fn add(name: String) -> Result<(), Box<Error>> {
-    let mut f = File::open(CONFIG_FILE_PATH)?;
-    let mut contents = String::new();
-    f.read_to_string(&mut contents)?;
-    let mut parsed: ConfigFile = toml::from_str(&contents)?;
+fn add(name: String, target_name: Option<&str>) -> Result<(), Box<Error>> {
+    let mut parsed = read_config_file()?;
 
     let name = name.to_lowercase();
-    let name_parts = name.split("/").collect::<Vec<&str>>();
+    let name_parts = name.splitn(2, "/").collect::<Vec<&str>>();
     if name_parts.len() != 2 {
         println!("please use the format <provider>/<addon>");
         return Ok(());
     }
 
-    if let Some(_) = LOCK.addons.iter().find(|&it| it.name == name) {
+    let targets = load_targets(&parsed, target_name)?;
+    // addons that are only installed as dependencies are installed again,
+    // so they're kept once nothing requires them anymore
+    let targets = targets.into_iter()
+        .filter(|target| !target.locks.iter().any(|it| it.name == name && !it.is_dependency()))
+        .collect::<Vec<Target>>();
+
+    if targets.is_empty() {
         println!("already installed!");
         return Ok(());
     }
 
-    let provider = String::from(name_parts[0]);
-    let name = String::from(name_parts[1]);
-
-    let addon = Addon { name, provider };
-    let addon_for_lock = addon.clone();
+    let addon = Addon::new(name_parts[0], name_parts[1]);
+    let config = parsed.config.clone().unwrap_or_default();
 
     let _temp_dir = create_temp_dir()?;
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    let mut added = false;
+
+    for target in &targets {
+        target.print_heading();
 
-    let add_future = |f: providers::AddonLockFuture| { f
-        .and_then(providers::download_addon)
-        .map_err(|err| println!("error downloading: {}", err))
-        .map(move |result| {
-            match result {
+        let installed_target = target.clone();
+        let add_future = |f: providers::LockFuture| { f
+            .and_then(|it| PROVIDERS.download_addon(it))
+            .and_then(move |result| match result {
                 Some((downloaded, lock)) => {
                     println!("downloaded {}, extracting...", lock.name);
-                    extract::extract_zip(downloaded, &ADDON_DIR);
+                    let lock = installed_target.install_downloaded(downloaded, lock)?;
                     println!("done with {}", lock.name);
-                    Ok(lock)
+                    Ok(Some(lock))
                 },
-                _ => Err(String::from("download failed")),
-            }
-        })
-        .map(move |lock| {
-            let lock_path = Path::new(&LOCK_FILE_PATH);
-            let _ = save_lock_file(&lock_path, &LOCK, &vec![lock.unwrap()]);
-
-            parsed.addons.push(addon);
-            let config_str = toml::to_string(&parsed).unwrap();
-            let mut f = File::create(CONFIG_FILE_PATH).unwrap();
-            f.write_all(config_str.as_bytes()).unwrap();
-        })
-    };
+                _ => Ok(None),
+            })
+        };
+
+        let addon_for_lock = target.apply_defaults(&config, addon.clone());
+        let add_future = match PROVIDERS.get_lock((addon_for_lock, None)).map(add_future) {
+            Some(add_future) => add_future,
+            _ => {
+                println!("addon not found");
+                return Ok(());
+            },
+        };
+
+        match runtime.block_on(add_future) {
+            Ok(Some(lock)) => {
+                let mut new_locks = vec![lock];
+                let dependencies = install_dependencies(
+                    target, &config, &parsed.dependencies, &mut runtime, &new_locks
+                );
+                new_locks.extend(dependencies);
 
-    match providers::get_lock((addon_for_lock, None)).map(add_future) {
-        Some(add_future) => tokio::run(add_future),
-        _ => println!("addon not found"),
+                save_lock_file(&target.name, &new_locks)?;
+                added = true;
+            },
+            Ok(None) => println!("download failed"),
+            Err(err) => println!("error adding addon: {}", err),
+        };
+    }
+
+    if !added {
+        return Ok(());
+    }
+
+    // addons added to a single target only end up in its own list
+    let addons = match target_name {
+        Some(name) if !parsed.targets.is_empty() => &mut parsed.targets.get_mut(name).unwrap().addons,
+        _ => &mut parsed.addons,
     };
 
+    if !addons.iter().any(|it| it.provider == addon.provider && it.name == addon.name) {
+        addons.push(addon);
+    }
+
+    save_config_file(&parsed)?;
+
     Ok(())
 }
 
-fn install() -> Result<(), Box<Error>> {
-    let mut f = File::open(CONFIG_FILE_PATH)?;
-    let mut contents = String::new();
-    f.read_to_string(&mut contents)?;
-    let parsed: ConfigFile = toml::from_str(&contents)?;
+/// Installs and updates the addons in the config. If `names` is not empty,
+/// only addons matching one of the names or patterns in it are processed,
+/// leaving the lock entries of all others as they are.
+fn install(locked: bool, names: Vec<String>, target_name: Option<&str>) -> Result<(), Box<Error>> {
+    let parsed = read_config_file()?;
 
     let config = match parsed.config {
-        Some(config) => config,
+        Some(ref config) => config.clone(),
         _ => GlobalConfig {
             parallel: Some(5),
+            ..Default::default()
         }
     };
 
-    let _temp_dir = create_temp_dir()?;
+    let targets = load_targets(&parsed, target_name)?;
+
+    for name in &names {
+        let matched = targets.iter()
+            .flat_map(|it| it.addons.iter())
+            .any(|it| matches_any(it, &vec![name.clone()]));
+
+        if !matched {
+            println!("no addons in {} match {}", CONFIG_FILE_PATH, name);
+        }
+    }
+
+    // with --locked nothing is resolved, everything is installed from the
+    // lock file as is. every target is checked before installing anything
+    let mut from_lock = Vec::new();
+    for target in &targets {
+        from_lock.push(if locked {
+            Some(locked_addons(target, &config)?.into_iter()
+                .filter(|(addon, _)| matches_any(addon, &names))
+                .collect::<Vec<(Addon, AddonLock)>>())
+        } else {
+            None
+        });
+    }
+
+    // a target that fails doesn't keep the others from being installed
+    let mut failed = false;
+    for (target, from_lock) in targets.iter().zip(from_lock) {
+        target.print_heading();
+        if let Err(err) = install_target(target, &config, &parsed.dependencies, from_lock, &names) {
+            println!("{}", err);
+            failed = true;
+        }
+    }
+
+    if failed {
+        return Err(From::from("not every addon could be installed"));
+    }
 
-    let parsed_with_locks = parsed.addons.into_iter().map(|it| {
-        let maybe_lock = find_existing_lock(&it);
-        (it, maybe_lock)
-    }).collect::<Vec<(Addon, Option<AddonLock>)>>();
+    Ok(())
+}
+
+fn install_target(
+    target: &Target, config: &GlobalConfig, dependency_addons: &BTreeMap<String, String>,
+    from_lock: Option<Vec<(Addon, AddonLock)>>, names: &Vec<String>
+) -> Result<(), Box<Error>> {
+    let _temp_dir = create_temp_dir()?;
 
-    let install_future = futures::future::ok::<_, String>(parsed_with_locks)
+    let (pinned, parsed_with_locks) = target.all_addons().iter()
+        .filter(|it| matches_any(it, names))
         .map(|it| {
-            if it.is_empty() {
-                println!("no addons");
-            } else {
-                println!("getting locks for {} addons...", it.len());
-            }
+            let maybe_lock = target.find_lock(it);
+            (target.apply_defaults(config, it.clone()), maybe_lock)
+        })
+        .partition::<Vec<(Addon, Option<AddonLock>)>, _>(|(addon, lock)| {
+            lock.as_ref().map(|it| addon.is_pinned_to(it)).unwrap_or(false)
+        });
+
+    // pinned addons aren't checked for updates, but they're still
+    // reinstalled from their lock if their folders have gone missing
+    let reinstall = pinned.into_iter()
+        .filter_map(|(addon, lock)| lock.map(|it| (addon, it)))
+        .filter(|(_, lock)| target.has_missing_folders(lock))
+        .collect::<Vec<(Addon, AddonLock)>>();
+
+    if parsed_with_locks.is_empty() {
+        println!("no addons to update");
+    } else {
+        println!("getting locks for {} addons...", parsed_with_locks.len());
+    }
+
+    // addons that can't be resolved, e.g. because they have no build for
+    // the flavor of the target, are reported without stopping the others.
+    // the ones of unknown providers are skipped by the registry and count
+    // as failed too
+    let to_resolve = parsed_with_locks.len();
+    let resolve_target = target.clone();
+    let resolve_future = futures::stream::iter_ok::<_, String>(parsed_with_locks)
+        .filter_map(|it| PROVIDERS.get_lock(it))
+        .map(|f| f
+            .map(Some)
+            .or_else(|err| {
+                println!("could not resolve addon: {}", err);
+                Ok(None)
+            }))
+        .buffer_unordered(config.parallel.unwrap_or(5))
+        .filter_map(|it| it)
+        .collect()
+        .map(move |resolved: Vec<(Addon, AddonLock)>| {
+            let failed = to_resolve - resolved.len();
+            let mut to_update = resolved.into_iter()
+                .filter(|(addon, lock)| resolve_target.find_lock(&addon)
+                    .map(|found| addon.should_update(lock, &found))
+                    .unwrap_or(true))
+                .collect::<Vec<(Addon, AddonLock)>>();
+
+            to_update.extend(reinstall);
+            (to_update, failed)
+        });
+
+    // installing from the lock file already includes the dependencies, and
+    // only addons that aren't installed exactly as locked are downloaded
+    let resolve_dependencies = from_lock.is_none();
+    let to_download = match from_lock {
+        Some(it) => Either::A(futures::future::ok((it.into_iter()
+            .filter(|(_, lock)| !target.is_intact(lock))
+            .collect::<Vec<(Addon, AddonLock)>>(), 0))),
+        _ => Either::B(resolve_future),
+    };
+
+    let installed_target = target.clone();
+    let parallel = config.parallel.unwrap_or(5);
+    let install_future = to_download
+        .and_then(move |(it, resolve_failed)| {
+            println!("downloading {} addons...", it.len());
+
+            // everything that doesn't end up with a new lock has failed
+            let to_install = it.len();
 
             futures::stream::iter_ok(it)
+                .filter_map(|it| PROVIDERS.download_addon(it))
+                // a failed download doesn't stop the others, since addons that
+                // were already swapped in still need their locks saved
+                .map(|f| f
+                    .map(Some)
+                    .or_else(|err| {
+                        println!("error downloading addon: {}", err);
+                        Ok(None)
+                    }))
+                .buffer_unordered(parallel)
+                .filter_map(|it| it)
+                .filter_map(move |(downloaded, lock)| {
+                    // the lock keeps the installed version of addons that failed
+                    match installed_target.install_downloaded(downloaded, lock) {
+                        Ok(mut lock) => {
+                            installed_target.keep_dependency(&mut lock);
+                            Some(lock)
+                        },
+                        Err(err) => {
+                            println!("{}", err);
+                            None
+                        },
+                    }
+                })
+                .collect()
+                .map(move |new_locks: Vec<AddonLock>| {
+                    let failed = resolve_failed + to_install - new_locks.len();
+                    (new_locks, failed)
+                })
+        });
+
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    let (mut new_locks, failed) = runtime.block_on(install_future)?;
+
+    if resolve_dependencies {
+        let dependencies = install_dependencies(
+            target, config, dependency_addons, &mut runtime, &new_locks
+        );
+        new_locks.extend(dependencies);
+    }
+
+    save_lock_file(&target.name, &new_locks)?;
+
+    if failed > 0 {
+        return Err(From::from(format!("{} addons could not be installed", failed)));
+    }
+
+    Ok(())
+}
+
+/// Installs the addons that the newly `installed` ones require, and the ones
+/// those require in turn. Required folders are read from the tocs and looked
+/// up in `dependency_addons`, unless an installed addon already provides
+/// them. Returns the locks of the dependencies, including installed ones
+/// whose addons requiring them changed.
+fn install_dependencies(
+    target: &Target, config: &GlobalConfig, dependency_addons: &BTreeMap<String, String>,
+    runtime: &mut tokio::runtime::Runtime, installed: &Vec<AddonLock>
+) -> Vec<AddonLock> {
+    let mut locks = target.locks.clone();
+    locks.retain(|it| !installed.iter().any(|lock| lock.name == it.name));
+    locks.extend(installed.iter().cloned());
+
+    let mut changed = Vec::new();
+    let mut pending = installed.clone();
+
+    // new versions don't necessarily require the same addons as the old
+    // ones, so what they require is read from their tocs again below
+    for lock in locks.iter_mut() {
+        let before = lock.required_by.len();
+        lock.required_by.retain(|it| !installed.iter().any(|new| &new.name == it));
+
+        if lock.required_by.len() != before {
+            // it stays a dependency even if nothing requires it anymore
+            lock.dependency = Some(true);
+            changed.push(lock.clone());
+        }
+    }
+
+    while !pending.is_empty() {
+        // addons to install, with the addons that require them
+        let mut missing: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for lock in &pending {
+            for folder in target.required_folders(lock) {
+                let source = dependency_addons.iter()
+                    .find(|(it, _)| it.eq_ignore_ascii_case(&folder))
+                    .map(|(_, it)| it.to_lowercase());
+
+                let provided_by = locks.iter()
+                    .position(|it| it.folders.iter().any(|it| it.eq_ignore_ascii_case(&folder)))
+                    .or_else(|| source.as_ref()
+                        .and_then(|source| locks.iter().position(|it| &it.name == source)));
+
+                if let Some(i) = provided_by {
+                    let provider = &mut locks[i];
+                    if provider.is_dependency() && !provider.required_by.contains(&lock.name) {
+                        provider.required_by.push(lock.name.clone());
+                        changed.push(provider.clone());
+                    }
+
+                    continue;
+                }
+
+                // folders that weren't installed by wam, e.g. copied by hand
+                if target.has_folder(&folder) {
+                    continue;
+                }
+
+                match source {
+                    Some(name) => missing.entry(name)
+                        .or_insert_with(Vec::new)
+                        .push(lock.name.clone()),
+                    _ => println!(
+                        "{} requires {}, add the addon that provides it to [dependencies] in {} to install it",
+                        lock.name, folder, CONFIG_FILE_PATH
+                    ),
+                }
+            }
+        }
+
+        let to_install = missing.into_iter()
+            .filter_map(|(name, required_by)| match parse_name(&name) {
+                Some(addon) => Some(((target.apply_defaults(config, addon), None), required_by)),
+                _ => {
+                    println!("can't install dependency {}, please use the format <provider>/<name>", name);
+                    None
+                },
+            })
+            .collect::<Vec<((Addon, Option<AddonLock>), Vec<String>)>>();
+
+        if to_install.is_empty() {
+            break;
+        }
+
+        println!("installing {} dependencies...", to_install.len());
+
+        // a dependency that fails to install doesn't stop the others
+        let installed_target = target.clone();
+        let install_future = futures::stream::iter_ok::<_, String>(to_install)
+            .filter_map(|(addon, required_by)| PROVIDERS.get_lock(addon)
+                .map(move |f| f.map(move |it| (it, required_by))))
+            .map(|f| f
+                .and_then(|(it, required_by)| match PROVIDERS.download_addon(it) {
+                    Some(download) => Either::A(download.map(move |it| Some((it, required_by)))),
+                    _ => Either::B(futures::future::ok(None)),
+                })
+                .or_else(|err| {
+                    println!("error installing dependency: {}", err);
+                    Ok(None)
+                }))
+            .buffer_unordered(config.parallel.unwrap_or(5))
+            .filter_map(|it| it)
+            .filter_map(move |((downloaded, lock), required_by)| {
+                match installed_target.install_downloaded(downloaded, lock) {
+                    Ok(mut lock) => {
+                        if !installed_target.is_configured(&lock.name) {
+                            lock.dependency = Some(true);
+                            lock.required_by = required_by;
+                        }
+
+                        Some(lock)
+                    },
+                    Err(err) => {
+                        println!("{}", err);
+                        None
+                    },
+                }
+            })
+            .collect();
+
+        pending = runtime.block_on(install_future).unwrap_or(Vec::new());
+        locks.extend(pending.iter().cloned());
+        changed.extend(pending.iter().cloned());
+    }
+
+    changed
+}
+
+/// Checks whether an addon is selected by one of the names or patterns given
+/// on the command line. No names select every addon.
+fn matches_any(addon: &Addon, names: &Vec<String>) -> bool {
+    let name = format!("{}/{}", addon.provider, addon.name);
+    names.is_empty() || names.iter().any(|it| glob::matches(it, &name))
+}
+
+/// Pairs every addon of a target with its lock for `install --locked`.
+/// Fails if the lock file doesn't match the config, or if a lock doesn't
+/// record enough to download the exact same files again.
+fn locked_addons(
+    target: &Target, config: &GlobalConfig
+) -> Result<Vec<(Addon, AddonLock)>, Box<Error>> {
+    let mut problems = Vec::new();
+    let mut result = Vec::new();
+
+    let addons = target.all_addons();
+    for addon in &addons {
+        let name = format!("{}/{}", addon.provider, addon.name);
+        let addon = target.apply_defaults(config, addon.clone());
+
+        let lock = match target.find_lock(&addon) {
+            Some(lock) => lock,
+            _ => {
+                problems.push(format!("{} is not in the lock file", name));
+                continue;
+            },
+        };
+
+        let provider = match PROVIDERS.get(&addon.provider) {
+            Some(provider) => provider,
+            _ => {
+                problems.push(format!("{} uses an unknown provider", name));
+                continue;
+            },
+        };
+
+        if let Some(ref version) = addon.version {
+            if version != &lock.version {
+                problems.push(format!(
+                    "{} is locked at {} instead of {}", name, lock.version, version
+                ));
+            }
+        }
+
+        let channel = addon.channel.unwrap_or_default();
+        if let Some(locked_channel) = lock.channel {
+            if locked_channel != channel {
+                problems.push(format!(
+                    "{} is locked to the {:?} channel instead of {:?}", name, locked_channel, channel
+                ));
+            }
+        }
+
+        if !provider.is_reproducible(&lock) {
+            problems.push(format!("the lock for {} doesn't record which file to download", name));
+        }
+
+        result.push((addon, lock));
+    }
+
+    for lock in &target.locks {
+        let in_config = addons.iter()
+            .any(|it| format!("{}/{}", it.provider, it.name) == lock.name);
+
+        if !in_config {
+            problems.push(format!(
+                "{} is in the lock file but not in {}", lock.name, CONFIG_FILE_PATH
+            ));
+        }
+    }
+
+    if !problems.is_empty() {
+        target.print_heading();
+        for problem in &problems {
+            println!("{}", problem);
+        }
+
+        return Err(From::from(format!(
+            "{} is out of sync with {}, run `wam install` to update it",
+            LOCK_FILE_PATH, CONFIG_FILE_PATH
+        )));
+    }
+
+    Ok(result)
+}
+
+/// Resolves the latest version of every addon and prints the ones that would
+/// be updated by `install`, without downloading anything. Returns whether
+/// there are any updates, or an error if any addon couldn't be checked.
+fn outdated(target_name: Option<&str>) -> Result<bool, Box<Error>> {
+    let parsed = read_config_file()?;
+    let config = parsed.config.clone().unwrap_or_default();
+
+    // every target is checked even if one of them fails
+    let mut any_updates = false;
+    let mut failed = false;
+    for target in load_targets(&parsed, target_name)? {
+        target.print_heading();
+        match outdated_target(&target, &config) {
+            Ok(updates) => any_updates |= updates,
+            Err(err) => {
+                println!("{}", err);
+                failed = true;
+            },
+        }
+    }
+
+    if failed {
+        return Err(From::from("not every addon could be checked for updates"));
+    }
+
+    Ok(any_updates)
+}
+
+fn outdated_target(target: &Target, config: &GlobalConfig) -> Result<bool, Box<Error>> {
+    // some providers download the addon to resolve it
+    let _temp_dir = create_temp_dir()?;
+
+    // pinned addons never get updated, so they're not checked
+    let unpinned = target.all_addons().iter()
+        .map(|it| {
+            let maybe_lock = target.find_lock(it);
+            (target.apply_defaults(config, it.clone()), maybe_lock)
         })
-        .flatten_stream()
-        .filter_map(providers::get_lock)
+        .filter(|(addon, lock)| lock.as_ref().map(|it| !addon.is_pinned_to(it)).unwrap_or(true))
+        .collect::<Vec<(Addon, Option<AddonLock>)>>();
+
+    // the addons of unknown providers are skipped by the registry, so they
+    // count as failed just like the ones whose lookup fails
+    let to_check = unpinned.len();
+    let outdated_future = futures::stream::iter_ok::<_, String>(unpinned)
+        .filter_map(|it| PROVIDERS.get_lock(it))
+        .map(|f| f
+            .map(Some)
+            .or_else(|err| {
+                println!("could not check for updates: {}", err);
+                Ok(None)
+            }))
         .buffer_unordered(config.parallel.unwrap_or(5))
-        .filter(|(addon, lock)| find_existing_lock(&addon)
-            .map(|found| lock.timestamp > found.timestamp)
-            .unwrap_or(true)
+        .filter_map(|it| it)
+        .collect();
+
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    let resolved = runtime.block_on(outdated_future)?;
+    let failed = to_check - resolved.len();
+
+    let mut updates = resolved.into_iter()
+        .filter_map(|(addon, lock)| {
+            let installed = target.find_lock(&addon);
+            match installed {
+                Some(ref found) if !addon.should_update(&lock, found) => None,
+                _ => Some((addon, installed, lock)),
+            }
+        })
+        .collect::<Vec<(Addon, Option<AddonLock>, AddonLock)>>();
+    updates.sort_by(|a, b| a.2.name.cmp(&b.2.name));
+
+    if !updates.is_empty() {
+        print_outdated(&updates);
+    } else if failed == 0 {
+        println!("all addons are up to date");
+    }
+
+    if failed > 0 {
+        return Err(From::from(format!("could not check {} addons for updates", failed)));
+    }
+
+    Ok(!updates.is_empty())
+}
+
+fn print_outdated(updates: &Vec<(Addon, Option<AddonLock>, AddonLock)>) {
+    let mut rows = vec![(
+        String::from("addon"), String::from("installed"), String::new(),
+        String::from("latest"), String::new(),
+    )];
+
+    rows.extend(updates.iter().map(|(_, installed, latest)| {
+        let (installed_version, installed_date) = match *installed {
+            Some(ref it) => (it.version.clone(), format_date(it.timestamp)),
+            _ => (String::from("-"), String::from("-")),
+        };
+
+        (
+            latest.name.clone(), installed_version, installed_date,
+            latest.version.clone(), format_date(latest.timestamp),
         )
-        .collect()
-        .map(|it| {
-            println!("downloading {} addons...", it.len());
-            it
+    }));
+
+    let name_width = rows.iter().map(|it| it.0.len()).max().unwrap_or(0);
+    let installed_width = rows.iter().map(|it| it.1.len()).max().unwrap_or(0);
+    let installed_date_width = rows.iter().map(|it| it.2.len()).max().unwrap_or(0);
+    let latest_width = rows.iter().map(|it| it.3.len()).max().unwrap_or(0);
+
+    for (name, installed, installed_date, latest, latest_date) in rows {
+        let line = format!(
+            "{:name_width$}  {:installed_width$}  {:installed_date_width$}  {:latest_width$}  {}",
+            name, installed, installed_date, latest, latest_date,
+            name_width = name_width,
+            installed_width = installed_width,
+            installed_date_width = installed_date_width,
+            latest_width = latest_width,
+        );
+
+        // the header has no dates, so it would end in padding
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Prints the installed addons whose tocs are behind the interface version of
+/// the client, which it hides unless "Load out of date AddOns" is enabled.
+/// Returns whether there are any, or an error if the interface version of
+/// a client is unknown.
+fn check(target_name: Option<&str>) -> Result<bool, Box<Error>> {
+    let parsed = read_config_file()?;
+
+    let mut any_outdated = false;
+    let mut failed = false;
+    for target in load_targets(&parsed, target_name)? {
+        target.print_heading();
+        match check_target(&target) {
+            Ok(outdated) => any_outdated |= outdated,
+            Err(err) => {
+                println!("{}", err);
+                failed = true;
+            },
+        }
+    }
+
+    if failed {
+        return Err(From::from("not every target could be checked"));
+    }
+
+    Ok(any_outdated)
+}
+
+fn check_target(target: &Target) -> Result<bool, Box<Error>> {
+    let current = match target.interface {
+        Some(current) => current,
+        _ => return Err(From::from(format!(
+            "could not find the interface version of the client, set `interface` in {}",
+            CONFIG_FILE_PATH
+        ))),
+    };
+
+    let outdated = target.locks.iter()
+        .flat_map(|lock| target.outdated_folders(lock).into_iter()
+            .map(move |(folder, interface)| (lock.name.clone(), folder, interface)))
+        .collect::<Vec<(String, String, u32)>>();
+
+    if outdated.is_empty() {
+        println!("all addons are up to date for interface {}", current);
+        return Ok(false);
+    }
+
+    let mut rows = vec![(String::from("addon"), String::from("folder"), String::from("interface"))];
+    rows.extend(outdated.into_iter().map(|(name, folder, interface)| {
+        (name, folder, interface.to_string())
+    }));
+
+    let name_width = rows.iter().map(|it| it.0.len()).max().unwrap_or(0);
+    let folder_width = rows.iter().map(|it| it.1.len()).max().unwrap_or(0);
+
+    println!("out of date for interface {}:", current);
+    for (name, folder, interface) in rows {
+        println!(
+            "{:name_width$}  {:folder_width$}  {}",
+            name, folder, interface,
+            name_width = name_width,
+            folder_width = folder_width,
+        );
+    }
+
+    Ok(true)
+}
+
+fn list(target_name: Option<&str>) -> Result<(), Box<Error>> {
+    let parsed = read_config_file()?;
+
+    for target in load_targets(&parsed, target_name)? {
+        target.print_heading();
+        list_target(&target);
+    }
+
+    Ok(())
+}
+
+fn list_target(target: &Target) {
+    if target.locks.is_empty() {
+        println!("no addons installed");
+        return;
+    }
+
+    let mut rows = vec![(
+        String::from("addon"), String::from("provider"), String::from("version"),
+        String::from("installed"), String::new(),
+    )];
+
+    rows.extend(target.locks.iter().map(|lock| {
+        let name_parts = lock.name.splitn(2, "/").collect::<Vec<&str>>();
+        let installed = lock.installed.map(format_date).unwrap_or(String::from("-"));
+        let status = if target.has_missing_folders(lock) {
+            "folders missing"
+        } else if lock.is_dependency() {
+            "dependency"
+        } else {
+            ""
+        };
+
+        (
+            String::from(*name_parts.last().unwrap()), String::from(name_parts[0]),
+            lock.version.clone(), installed, String::from(status),
+        )
+    }));
+
+    let name_width = rows.iter().map(|it| it.0.len()).max().unwrap_or(0);
+    let provider_width = rows.iter().map(|it| it.1.len()).max().unwrap_or(0);
+    let version_width = rows.iter().map(|it| it.2.len()).max().unwrap_or(0);
+    let installed_width = rows.iter().map(|it| it.3.len()).max().unwrap_or(0);
+
+    for (name, provider, version, installed, status) in rows {
+        let line = format!(
+            "{:name_width$}  {:provider_width$}  {:version_width$}  {:installed_width$}  {}",
+            name, provider, version, installed, status,
+            name_width = name_width,
+            provider_width = provider_width,
+            version_width = version_width,
+            installed_width = installed_width,
+        );
+
+        println!("{}", line.trim_end());
+    }
+}
+
+fn info(name: String, target_name: Option<&str>) -> Result<(), Box<Error>> {
+    let parsed = read_config_file()?;
+
+    let targets = load_targets(&parsed, target_name)?;
+    let installed = targets.iter()
+        .filter_map(|target| target.locks.iter()
+            .find(|it| it.name.eq_ignore_ascii_case(&name))
+            .map(|lock| (target, lock)))
+        .collect::<Vec<(&Target, &AddonLock)>>();
+
+    if installed.is_empty() {
+        println!("{} is not installed", name);
+    }
+
+    for (target, lock) in installed {
+        target.print_heading();
+        print_info(target, lock);
+    }
+
+    Ok(())
+}
+
+fn print_info(target: &Target, lock: &AddonLock) {
+    println!("name:       {}", lock.name);
+    println!("version:    {}", lock.version);
+    println!("resolved:   {}", lock.resolved);
+    println!("released:   {}", format_date(lock.timestamp));
+    println!("installed:  {}", lock.installed.map(format_date).unwrap_or(String::from("-")));
+
+    if let Some(channel) = lock.channel {
+        println!("channel:    {}", format!("{:?}", channel).to_lowercase());
+    }
+
+    if let Some(ref url) = lock.url {
+        println!("source:     {}", url);
+    }
+
+    if let Some(ref hash) = lock.hash {
+        println!("hash:       {}", hash);
+    }
+
+    if lock.is_dependency() && lock.required_by.is_empty() {
+        println!("needed by:  nothing, run `wam prune` to remove it");
+    } else if lock.is_dependency() {
+        println!("needed by:  {}", lock.required_by.join(", "));
+    }
+
+    if !lock.files.is_empty() {
+        let (mut missing, mut modified) = (0, 0);
+        for (file, hash) in &lock.files {
+            match hash::hash_file(&target.addon_dir.join(file)) {
+                Ok(ref it) if it == hash => (),
+                Ok(_) => modified += 1,
+                _ => missing += 1,
+            }
+        }
+
+        if missing + modified == 0 {
+            println!("files:      {}", lock.files.len());
+        } else {
+            println!(
+                "files:      {} ({} modified, {} missing)",
+                lock.files.len(), modified, missing
+            );
+        }
+    }
+
+    if lock.folders.is_empty() {
+        println!("folders:    -");
+        return;
+    }
+
+    println!("folders:");
+    for folder in &lock.folders {
+        let path = target.addon_dir.join(folder);
+        if !path.is_dir() {
+            println!("  {} (missing)", folder);
+            continue;
+        }
+
+        match toc::read(&path, target.flavor) {
+            Some(toc) => println!(
+                "  {} - {}, interface {}",
+                folder,
+                toc.title.clone().unwrap_or(folder.clone()),
+                toc.max_interface().map(|it| it.to_string()).unwrap_or(String::from("unknown")),
+            ),
+            _ => println!("  {} (no toc)", folder),
+        }
+    }
+}
+
+/// Removes an addon from the config and deletes its folders. Returns whether
+/// it was found at all.
+fn remove(name: String, target_name: Option<&str>) -> Result<bool, Box<Error>> {
+    let mut parsed = read_config_file()?;
+
+    let name_parts = name.splitn(2, "/").collect::<Vec<&str>>();
+    if name_parts.len() != 2 {
+        println!("please use the format <provider>/<addon>");
+        return Ok(false);
+    }
+
+    // names in the config keep the case they were written with, e.g. the
+    // folder names of git addons, so they're matched case insensitively
+    let (provider, addon_name) = (name_parts[0], name_parts[1]);
+    let is_addon = |it: &Addon| {
+        it.provider.eq_ignore_ascii_case(provider) && it.name.eq_ignore_ascii_case(addon_name)
+    };
+
+    // shared addons can't be removed from a single target, since the config
+    // has no way to exclude them
+    let single_target = target_name.is_some() && !parsed.targets.is_empty();
+    if single_target && parsed.addons.iter().any(&is_addon) {
+        println!("{} is installed into every target, remove it without --target", name);
+        return Ok(false);
+    }
+
+    let targets = load_targets(&parsed, target_name)?.into_iter()
+        .filter(|target| target.addons.iter().any(&is_addon)
+            || target.locks.iter().any(|it| it.name.eq_ignore_ascii_case(&name)))
+        .collect::<Vec<Target>>();
+
+    if targets.is_empty() {
+        println!("{} is not installed", name);
+        return Ok(false);
+    }
+
+    // the config is saved first, so if that fails nothing has been deleted
+    // and the lock file still matches what's installed
+    for target in &targets {
+        if let Some(ref target_name) = target.name {
+            if let Some(target_config) = parsed.targets.get_mut(target_name) {
+                target_config.addons.retain(|it| !is_addon(it));
+            }
+        }
+    }
+
+    if !single_target {
+        parsed.addons.retain(|it| !is_addon(it));
+    }
+
+    save_config_file(&parsed)?;
+
+    for target in &targets {
+        target.print_heading();
+
+        let existing = target.locks.iter().find(|it| it.name.eq_ignore_ascii_case(&name));
+        if let Some(lock) = existing {
+            let remaining = target.locks.iter()
+                .filter(|it| it.name != lock.name)
+                .collect::<Vec<&AddonLock>>();
+
+            delete_folders(target, lock, &remaining)?;
+
+            if target.locks.iter().any(|it| it.required_by.contains(&lock.name)) {
+                println!("run `wam prune` to remove dependencies that aren't needed anymore");
+            }
+
+            let mut locks = read_lock_file()?;
+            locks.addons_for_mut(&target.name).retain(|it| it.name != lock.name);
+            write_lock_file(Path::new(&LOCK_FILE_PATH), &locks)?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Deletes the folders of an installed addon, except for the ones that one of
+/// the `remaining` addons also extracted.
+fn delete_folders(
+    target: &Target, lock: &AddonLock, remaining: &Vec<&AddonLock>
+) -> Result<(), Box<Error>> {
+    if lock.folders.is_empty() {
+        println!("no folders recorded for {}, leaving addon directory untouched", lock.name);
+    }
+
+    for folder in &lock.folders {
+        let shared = remaining.iter().any(|it| it.folders.contains(folder));
+
+        let path = target.addon_dir.join(folder);
+        if shared || !path.is_dir() {
+            continue;
+        }
+
+        println!("deleting {}", path.display());
+        fs::remove_dir_all(&path)?;
+    }
+
+    Ok(())
+}
+
+/// Removes addons that were installed as dependencies but aren't required by
+/// anything in the config anymore, directly or through other dependencies.
+fn prune(dry_run: bool, target_name: Option<&str>) -> Result<(), Box<Error>> {
+    let parsed = read_config_file()?;
+
+    for target in load_targets(&parsed, target_name)? {
+        target.print_heading();
+        prune_target(&target, dry_run)?;
+    }
+
+    Ok(())
+}
+
+fn prune_target(target: &Target, dry_run: bool) -> Result<(), Box<Error>> {
+    // everything that isn't only a dependency is needed, and so is everything
+    // that a needed addon requires
+    let mut needed = target.locks.iter()
+        .filter(|it| !it.is_dependency() || target.is_configured(&it.name))
+        .map(|it| it.name.clone())
+        .collect::<Vec<String>>();
+
+    loop {
+        let required = target.locks.iter()
+            .filter(|it| !needed.contains(&it.name))
+            .filter(|it| it.required_by.iter().any(|name| needed.contains(name)))
+            .map(|it| it.name.clone())
+            .collect::<Vec<String>>();
+
+        if required.is_empty() {
+            break;
+        }
+
+        needed.extend(required);
+    }
+
+    let (kept, orphaned) = target.locks.iter()
+        .partition::<Vec<&AddonLock>, _>(|it| needed.contains(&it.name));
+
+    if orphaned.is_empty() {
+        println!("no unneeded dependencies");
+        return Ok(());
+    }
+
+    for lock in &orphaned {
+        if dry_run {
+            println!("would remove {} ({})", lock.name, lock.folders.join(", "));
+            continue;
+        }
+
+        println!("removing {}", lock.name);
+        delete_folders(target, lock, &kept)?;
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    // the removed addons don't require anything anymore
+    let mut lock_file = read_lock_file()?;
+    {
+        let locks = lock_file.addons_for_mut(&target.name);
+        locks.retain(|it| needed.contains(&it.name));
+        for lock in locks.iter_mut() {
+            lock.required_by.retain(|it| needed.contains(it));
+        }
+    }
+
+    write_lock_file(Path::new(&LOCK_FILE_PATH), &lock_file)?;
+
+    Ok(())
+}
+
+fn search(term: String) -> Result<(), Box<Error>> {
+    let term = term.to_lowercase();
+
+    let searches = PROVIDERS.search(&term).into_iter()
+        .map(|(provider, f)| f.or_else(move |err| {
+            println!("searching {} failed: {}", provider, err);
+            Ok(Vec::new())
+        }))
+        .collect::<Vec<_>>();
+
+    let rank_term = term.clone();
+    let search_future = futures::future::join_all(searches)
+        .map(move |results| {
+            let mut results = results.into_iter()
+                .flat_map(|it| it)
+                .collect::<Vec<providers::SearchResult>>();
+
+            results.sort_by_key(|it| (search_rank(&rank_term, it), Reverse(it.timestamp)));
+            results.truncate(MAX_SEARCH_RESULTS);
+
+            futures::stream::iter_ok(results)
         })
-        .map(futures::stream::iter_ok)
         .flatten_stream()
-        .filter_map(providers::download_addon)
-        .buffer_unordered(config.parallel.unwrap_or(5))
-        .map(move |(downloaded, lock)| {
-            extract::extract_zip(downloaded, &ADDON_DIR);
-            lock
+        .map(|result| {
+            // fill in the latest version by resolving a lock for results
+            // where the search page didn't include it
+            let name_parts = result.name.splitn(2, "/").collect::<Vec<&str>>();
+            let addon = Addon::new(name_parts[0], name_parts[1]);
+
+            match PROVIDERS.get_lock((addon, None)) {
+                Some(f) if result.version.is_none() => {
+                    let fallback = result.clone();
+                    Either::A(f
+                        .map(move |(_, lock)| providers::SearchResult {
+                            version: Some(lock.version),
+                            timestamp: Some(lock.timestamp),
+                            ..result
+                        })
+                        .or_else(move |_| Ok(fallback)))
+                },
+                _ => Either::B(futures::future::ok(result)),
+            }
         })
+        .buffered(5)
         .collect()
-        .map(|new_locks| {
-            let lock_path = Path::new(&LOCK_FILE_PATH);
-            let _ = save_lock_file(&lock_path, &LOCK, &new_locks);
+        .map(move |results| {
+            if results.is_empty() {
+                println!("no addons found for \"{}\"", term);
+            } else {
+                print_search_results(&results);
+            }
         })
-        .map_err(|_| ());
+        .map_err(|_: String| ());
 
-    tokio::run(install_future);
+    tokio::run(search_future);
 
     Ok(())
 }
 
-fn find_existing_lock(addon: &Addon) -> Option<AddonLock> {
-    LOCK.addons.iter().find(|it| {
-        it.name == format!("{}/{}", addon.provider, addon.name)
-    }).map(Clone::clone)
+fn search_rank(term: &str, result: &providers::SearchResult) -> usize {
+    let name = result.name.splitn(2, "/").last().unwrap_or("");
+    let title = result.title.to_lowercase();
+
+    if name == term || title == term {
+        0
+    } else if name.starts_with(term) || title.starts_with(term) {
+        1
+    } else if name.contains(term) || title.contains(term) {
+        2
+    } else {
+        3
+    }
+}
+
+fn print_search_results(results: &Vec<providers::SearchResult>) {
+    let rows = results.iter().map(|it| {
+        let version = it.version.clone().unwrap_or(String::from("-"));
+        let updated = it.timestamp
+            .map(format_date)
+            .unwrap_or(String::from("-"));
+
+        (it.name.clone(), it.title.clone(), version, updated)
+    }).collect::<Vec<_>>();
+
+    let name_width = rows.iter().map(|it| it.0.len()).max().unwrap_or(0);
+    let title_width = rows.iter().map(|it| it.1.len()).max().unwrap_or(0);
+    let version_width = rows.iter().map(|it| it.2.len()).max().unwrap_or(0);
+
+    for (name, title, version, updated) in rows {
+        println!(
+            "{:name_width$}  {:title_width$}  {:version_width$}  {}",
+            name, title, version, updated,
+            name_width = name_width,
+            title_width = title_width,
+            version_width = version_width,
+        );
+    }
+}
+
+/// Creates an addon from a name in the format `<provider>/<name>`.
+fn parse_name(name: &str) -> Option<Addon> {
+    let name_parts = name.splitn(2, "/").collect::<Vec<&str>>();
+    if name_parts.len() != 2 {
+        return None;
+    }
+
+    Some(Addon::new(name_parts[0], name_parts[1]))
+}
+
+fn format_date(timestamp: u64) -> String {
+    Utc.timestamp(timestamp as i64, 0).format("%Y-%m-%d").to_string()
+}
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+fn read_config_file() -> Result<ConfigFile, Box<Error>> {
+    let mut f = File::open(CONFIG_FILE_PATH)?;
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    Ok(toml::from_str(&contents)?)
+}
+
+fn read_lock_file() -> Result<LockFile, Box<Error>> {
+    let lock_path = Path::new(LOCK_FILE_PATH);
+    if !lock_path.is_file() {
+        return Ok(LockFile::default());
+    }
+
+    let mut contents = String::new();
+    File::open(lock_path)?.read_to_string(&mut contents)?;
+
+    Ok(toml::from_str(&contents)?)
+}
+
+/// Loads every target from the config, or only the one named `selected`.
+fn load_targets(parsed: &ConfigFile, selected: Option<&str>) -> Result<Vec<Target>, Box<Error>> {
+    let config = parsed.config.clone().unwrap_or_default();
+    let lock_file = read_lock_file()?;
+
+    let target_configs = if parsed.targets.is_empty() {
+        if let Some(name) = selected {
+            return Err(From::from(format!("no target named {} in {}", name, CONFIG_FILE_PATH)));
+        }
+
+        vec![(None, TargetConfig::default())]
+    } else {
+        parsed.targets.iter()
+            .filter(|(name, _)| selected.map(|it| it == name.as_str()).unwrap_or(true))
+            .map(|(name, it)| (Some(name.clone()), it.clone()))
+            .collect::<Vec<(Option<String>, TargetConfig)>>()
+    };
+
+    if target_configs.is_empty() {
+        return Err(From::from(format!(
+            "no target named {} in {}", selected.unwrap_or(""), CONFIG_FILE_PATH
+        )));
+    }
+
+    target_configs.into_iter().map(|(name, target_config)| {
+        let flavor = target_config.flavor.or(config.flavor).unwrap_or_default();
+        let wow_path = target_config.wow_path.clone().or(config.wow_path.clone());
+
+        let game_dir = find_game_dir(wow_path, flavor)?;
+        let interface = target_config.interface
+            .or(config.interface)
+            .or_else(|| detect_interface(&game_dir, flavor));
+
+        let addon_dir = find_addon_dir(&game_dir);
+        if !addon_dir.is_dir() {
+            fs::create_dir_all(&addon_dir)?;
+        }
+
+        // addons of the target take precedence over shared ones of the same name
+        let mut addons = parsed.addons.iter()
+            .filter(|shared| !target_config.addons.iter()
+                .any(|it| it.provider == shared.provider && it.name == shared.name))
+            .cloned()
+            .collect::<Vec<Addon>>();
+        addons.extend(target_config.addons);
+
+        let locks = lock_file.addons_for(&name);
+        Ok(Target { name, flavor, interface, addon_dir, addons, locks })
+    }).collect()
+}
+
+/// Finds the folder of a flavor's client in an installation. `wow_path`
+/// can point at the installation itself or directly at the flavor folder
+/// inside of it, and defaults to the current directory.
+fn find_game_dir(wow_path: Option<String>, flavor: Flavor) -> Result<PathBuf, String> {
+    let wow_path = env::var(WOW_PATH_ENV).ok().or(wow_path);
+
+    let game_dir = match wow_path {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let is_flavor_dir = path.file_name()
+                .map(|it| it.to_string_lossy().starts_with("_"))
+                .unwrap_or(false);
+
+            let game_dir = if is_flavor_dir {
+                path
+            } else {
+                path.join(flavor.dir_name())
+            };
+
+            if !game_dir.is_dir() {
+                return Err(format!("{} does not exist", game_dir.display()));
+            }
+
+            game_dir
+        },
+        _ => PathBuf::from("."),
+    };
+
+    Ok(game_dir)
+}
+
+fn find_addon_dir(game_dir: &Path) -> PathBuf {
+    let existing = ADDON_DIR_PATHS.iter()
+        .map(|it| game_dir.join(it))
+        .find(|it| it.is_dir());
+
+    existing.unwrap_or(game_dir.join(ADDON_DIR_PATHS[0]))
+}
+
+/// Reads the interface version of a flavor's client from the `.build.info`
+/// that the launcher keeps in the root of the installation, one level above
+/// the flavor folders.
+fn detect_interface(game_dir: &Path, flavor: Flavor) -> Option<u32> {
+    let path = [game_dir.join(".build.info"), game_dir.join("..").join(".build.info")]
+        .iter()
+        .find(|it| it.is_file())
+        .map(Clone::clone)?;
+
+    let mut contents = String::new();
+    File::open(path).ok()?.read_to_string(&mut contents).ok()?;
+
+    // the first line names the columns like `Version!STRING:0`, the others
+    // describe one installed client each, separated by `|`
+    let mut lines = contents.lines();
+    let columns = lines.next()?
+        .split("|")
+        .map(|it| it.split("!").next().unwrap_or(""))
+        .collect::<Vec<&str>>();
+
+    let version_column = columns.iter().position(|it| *it == "Version")?;
+    let product_column = columns.iter().position(|it| *it == "Product")?;
+
+    lines.map(|line| line.split("|").collect::<Vec<&str>>())
+        .find(|values| values.get(product_column) == Some(&flavor.product()))
+        .and_then(|values| values.get(version_column).map(|it| it.to_string()))
+        .and_then(|version| toc::interface_from_version(&version))
 }
 
 fn create_temp_dir() -> Result<PathBuf, Box<Error>> {
@@ -279,25 +1741,34 @@ fn delete_temp_dir() -> Result<(), Box<Error>> {
     Ok(())
 }
 
+/// Merges `new_locks` into the section of `target` in the lock file. The
+/// file is read again first, since other targets may have been updated
+/// since the command started.
 fn save_lock_file(
-    path: &Path, old_lock: &LockFile,
-    new_locks: &Vec<AddonLock>
+    target: &Option<String>, new_locks: &Vec<AddonLock>
 ) -> Result<(), Box<Error>> {
-    let mut locks = old_lock.clone();
-    for lock in new_locks {
-        let existing = old_lock.addons
-            .iter().enumerate().find(|(_, it)| {
-                it.name == lock.name
-            });
-
-        if let Some((i, _)) = existing {
-            locks.addons[i] = lock.clone();
-        } else {
-            locks.addons.push(lock.clone());
+    let mut lock_file = read_lock_file()?;
+    {
+        let locks = lock_file.addons_for_mut(target);
+        for lock in new_locks {
+            let existing = locks
+                .iter().position(|it| {
+                    it.name == lock.name
+                });
+
+            if let Some(i) = existing {
+                locks[i] = lock.clone();
+            } else {
+                locks.push(lock.clone());
+            }
         }
     }
 
-    let lock_str = toml::to_string(&locks)?;
+    write_lock_file(Path::new(&LOCK_FILE_PATH), &lock_file)
+}
+
+fn write_lock_file(path: &Path, locks: &LockFile) -> Result<(), Box<Error>> {
+    let lock_str = toml::to_string(locks)?;
     
     // recreate the file because we want to overwrite anyways
     let mut f = File::create(path)?;
@@ -305,3 +1776,61 @@ fn save_lock_file(
 
     Ok(())
 }
+
+fn save_config_file(config: &ConfigFile) -> Result<(), Box<Error>> {
+    let config_str = toml::to_string(config)?;
+    let mut f = File::create(CONFIG_FILE_PATH)?;
+    f.write_all(config_str.as_bytes())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGETS_ONLY: &'static str = r#"
+[config]
+wow_path = "wow"
+
+[targets.retail]
+
+[[targets.retail.addons]]
+name = "Parent"
+provider = "local"
+path = "src/Parent"
+
+[targets.era]
+flavor = "classic_era"
+
+[[targets.era.addons]]
+name = "Parent"
+provider = "local"
+path = "src/Parent"
+"#;
+
+    #[test]
+    fn round_trips_targets_only_config() {
+        let mut parsed: ConfigFile = toml::from_str(TARGETS_ONLY).unwrap();
+        assert!(parsed.addons.is_empty());
+
+        parsed.targets.get_mut("era").unwrap().addons.clear();
+
+        let saved = toml::to_string(&parsed).unwrap();
+        let reparsed: ConfigFile = toml::from_str(&saved).unwrap();
+
+        assert_eq!(reparsed.config.unwrap().wow_path, Some(String::from("wow")));
+        assert!(reparsed.addons.is_empty());
+        assert!(reparsed.targets["era"].addons.is_empty());
+        assert_eq!(reparsed.targets["era"].flavor, Some(Flavor::ClassicEra));
+        assert_eq!(reparsed.targets["retail"].addons[0].name, "Parent");
+    }
+
+    #[test]
+    fn round_trips_config_without_addons() {
+        let parsed: ConfigFile = toml::from_str("[config]\nwow_path = \"wow\"\n").unwrap();
+        let saved = toml::to_string(&parsed).unwrap();
+
+        assert_eq!(saved, "[config]\nwow_path = \"wow\"\n");
+    }
+}
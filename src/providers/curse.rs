@@ -1,42 +1,198 @@
-use super::select::predicate::*;
-use super::select::document::Document;
+use super::chrono::prelude::*;
 
-use ::{Addon, AddonLock};
+use ::{Addon, AddonLock, Channel, Flavor};
+use super::{Provider, ProviderMetadata, SearchResult};
+use super::{LockFuture, DownloadFuture, SearchFuture};
+use super::get_json;
 use ::futures::{Future, Async, Stream};
+use ::std::env;
 use ::std::path::{Path, PathBuf};
 use ::std::fs::File;
 use ::std::io::Write;
 
 use ::reqwest::async::{Response, Client, Chunk};
 
+// only used for locks that were created before download urls were stored
 pub const CURSE_DL_URL_TEMPLATE: &'static str =
     "https://wow.curseforge.com/projects/{}/files/latest";
 
 pub const ACE_DL_URL_TEMPLATE: &'static str =
     "https://wowace.com/projects/{}/files/latest";
 
-// by sorting by release type, we get releases before alphas and avoid a problem
-// where the first page could be filled with alpha releases (thanks dbm very cool)
-const CURSE_FILES_URL_TEMPLATE: &'static str =
-    "https://wow.curseforge.com/projects/{}/files?sort=releasetype";
+const DEFAULT_API_URL: &'static str = "https://addons-ecs.forgesvc.net/api/v2";
+const API_URL_ENV: &'static str = "WAM_CURSE_API_URL";
 
-const ACE_FILES_URL_TEMPLATE: &'static str =
-    "https://wowace.com/projects/{}/files?sort=releasetype";
+const SEARCH_URL_TEMPLATE: &'static str =
+    "{api}/addon/search?gameId=1&searchFilter={}";
+
+const FILES_URL_TEMPLATE: &'static str = "{api}/addon/{}/files";
+
+const RETAIL_FLAVOR: &'static str = "wow_retail";
+
+/// The `gameVersionFlavor`s of files that can be installed for `flavor`.
+/// Every progression server gets its own flavor on curse, but only the
+/// latest one is still running, so builds for the earlier ones like
+/// `wow_wrath_classic` don't count and addons that stopped there fail.
+fn curse_flavors(flavor: Flavor) -> &'static [&'static str] {
+    match flavor.base() {
+        Flavor::ClassicEra => &["wow_classic"],
+        Flavor::Classic => &["wow_cata"],
+        _ => &[RETAIL_FLAVOR],
+    }
+}
+
+/// Provider for projects hosted on curseforge or wowace. Both sites are
+/// served by the same api and share project slugs, so they only differ in
+/// the name the addons are listed under.
+pub struct CurseProvider {
+    name: &'static str,
+    homepage: &'static str,
+    dl_url_template: &'static str,
+    api_url: String,
+    // wowace projects show up in curseforge searches as well, so only one
+    // of them is searched to avoid listing every result twice
+    searchable: bool,
+}
+
+impl CurseProvider {
+    pub fn curse() -> CurseProvider {
+        CurseProvider {
+            name: "curse",
+            homepage: "https://wow.curseforge.com",
+            dl_url_template: CURSE_DL_URL_TEMPLATE,
+            api_url: api_url_from_env(),
+            searchable: true,
+        }
+    }
+
+    pub fn ace() -> CurseProvider {
+        CurseProvider {
+            name: "ace",
+            homepage: "https://wowace.com",
+            dl_url_template: ACE_DL_URL_TEMPLATE,
+            api_url: api_url_from_env(),
+            searchable: false,
+        }
+    }
+
+    pub fn with_api_url(mut self, api_url: &str) -> CurseProvider {
+        self.api_url = String::from(api_url.trim_end_matches('/'));
+        self
+    }
+}
+
+fn api_url_from_env() -> String {
+    let api_url = env::var(API_URL_ENV)
+        .unwrap_or(String::from(DEFAULT_API_URL));
+
+    String::from(api_url.trim_end_matches('/'))
+}
+
+impl Provider for CurseProvider {
+    fn metadata(&self) -> ProviderMetadata {
+        ProviderMetadata {
+            title: String::from(self.name),
+            homepage: String::from(self.homepage),
+        }
+    }
+
+    fn get_lock(&self, addon: Addon, _old_lock: Option<AddonLock>) -> LockFuture {
+        Box::new(CurseLockFuture {
+            inner: LockInner::Idle,
+            client: Client::new(),
+            api_url: self.api_url.clone(),
+            provider: self.name,
+            addon,
+        })
+    }
+
+    fn download_addon(&self, addon: Addon, lock: AddonLock) -> DownloadFuture {
+        let url = lock.url.clone()
+            .unwrap_or(self.dl_url_template.replace("{}", &addon.name));
+
+        Box::new(download_addon(addon, lock, url))
+    }
+
+    fn search(&self, term: &str) -> Option<SearchFuture> {
+        if !self.searchable {
+            return None;
+        }
+
+        let url = SEARCH_URL_TEMPLATE
+            .replace("{api}", &self.api_url)
+            .replace("{}", &term.replace(" ", "+"));
+
+        Some(Box::new(search(self.name, url)))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Project {
+    id: u64,
+    name: String,
+    slug: String,
+    #[serde(default)]
+    latest_files: Vec<ProjectFile>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProjectFile {
+    id: u64,
+    display_name: String,
+    file_date: String,
+    release_type: u32,
+    download_url: String,
+    game_version_flavor: Option<String>,
+}
+
+impl ProjectFile {
+    fn timestamp(&self) -> Option<u64> {
+        DateTime::parse_from_rfc3339(&self.file_date).ok()
+            .map(|it| it.timestamp() as u64)
+    }
+
+    fn channel(&self) -> Channel {
+        match self.release_type {
+            1 => Channel::Release,
+            2 => Channel::Beta,
+            _ => Channel::Alpha,
+        }
+    }
+
+    fn is_for(&self, flavor: Flavor) -> bool {
+        // files without a flavor predate classic, so they're retail files
+        let file_flavor = self.game_version_flavor.as_ref()
+            .map(|it| it.as_str())
+            .unwrap_or(RETAIL_FLAVOR);
+
+        curse_flavors(flavor).contains(&file_flavor)
+    }
+}
+
+/// Picks the newest file for `flavor` that is allowed by `channel`.
+fn latest_file(files: &Vec<ProjectFile>, channel: Channel, flavor: Flavor) -> Option<&ProjectFile> {
+    files.iter()
+        .filter(|it| it.is_for(flavor) && channel.allows(it.channel()))
+        .filter(|it| it.timestamp().is_some())
+        .max_by_key(|it| it.timestamp())
+}
 
 pub struct CurseDownloadFuture {
     inner: DownloadInner,
     client: Client,
-    addon: Addon,
     lock: AddonLock,
+    url: String,
     filename: Option<String>,
 }
 
-pub fn download_addon(addon: Addon, lock: AddonLock) -> CurseDownloadFuture {
+fn download_addon(_addon: Addon, lock: AddonLock, url: String) -> CurseDownloadFuture {
     CurseDownloadFuture {
         inner: DownloadInner::Idle,
         client: Client::new(),
-        addon,
         lock,
+        url,
         filename: None,
     }
 }
@@ -57,13 +213,7 @@ impl Future for CurseDownloadFuture {
         loop {
             let next = match self.inner {
                 Idle => {
-                    let url = if self.addon.provider == "curse" {
-                        CURSE_DL_URL_TEMPLATE.replace("{}", &self.addon.name)
-                    } else {
-                        ACE_DL_URL_TEMPLATE.replace("{}", &self.addon.name)
-                    };
-
-                    let pending = self.client.get(&url).send()
+                    let pending = self.client.get(&self.url).send()
                         .map_err(|err| format!("{}", err));
 
                     ReadingFilename(Box::new(pending))
@@ -83,8 +233,9 @@ impl Future for CurseDownloadFuture {
                     let body = try_ready!(f.map_err(|err| format!("{}", err)).poll());
                     let filename = self.filename.take().unwrap();
                     let filepath = Path::new(".wam-temp").join(&filename);
-                    let mut file = File::create(&filepath).expect("could not create file");
-                    file.write_all(&body).expect("could not write to file");
+                    File::create(&filepath)
+                        .and_then(|mut file| file.write_all(&body))
+                        .map_err(|err| format!("could not write {}: {}", filepath.display(), err))?;
 
                     return Ok(Async::Ready((filepath, self.lock.clone())));
                 },
@@ -98,20 +249,16 @@ impl Future for CurseDownloadFuture {
 pub struct CurseLockFuture {
     inner: LockInner,
     client: Client,
+    api_url: String,
+    provider: &'static str,
     addon: Addon,
 }
 
 enum LockInner {
     Idle,
-    Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
-}
-
-pub fn get_lock(addon: Addon) -> CurseLockFuture {
-    CurseLockFuture {
-        inner: LockInner::Idle,
-        client: Client::new(),
-        addon,
-    }
+    // the api only works with project ids, so the slug has to be looked up first
+    Resolving(Box<Future<Item = Vec<Project>, Error = String> + Send>),
+    Files(Box<Future<Item = Vec<ProjectFile>, Error = String> + Send>),
 }
 
 impl Future for CurseLockFuture {
@@ -124,48 +271,116 @@ impl Future for CurseLockFuture {
         loop {
             let next = match self.inner {
                 Idle => {
-                    let url = if self.addon.provider == "curse" {
-                        CURSE_FILES_URL_TEMPLATE.replace("{}", &self.addon.name)
-                    } else {
-                        ACE_FILES_URL_TEMPLATE.replace("{}", &self.addon.name)
+                    let url = SEARCH_URL_TEMPLATE
+                        .replace("{api}", &self.api_url)
+                        .replace("{}", &self.addon.name);
+
+                    Resolving(get_json(&self.client, &url))
+                },
+                Resolving(ref mut f) => {
+                    let projects = try_ready!(f.poll());
+                    let project = projects.iter()
+                        .find(|it| it.slug == self.addon.name)
+                        .ok_or(format!("project {}/{} not found", self.provider, self.addon.name))?;
+
+                    let url = FILES_URL_TEMPLATE
+                        .replace("{api}", &self.api_url)
+                        .replace("{}", &project.id.to_string());
+
+                    Files(get_json(&self.client, &url))
+                },
+                Files(ref mut f) => {
+                    let files = try_ready!(f.poll());
+                    let channel = self.addon.channel.unwrap_or_default();
+                    let flavor = self.addon.flavor.unwrap_or_default();
+
+                    if !files.iter().any(|it| it.is_for(flavor)) {
+                        return Err(format!(
+                            "{}/{} has no files for {}", self.provider, self.addon.name, flavor.name()
+                        ));
+                    }
+
+                    let file = match self.addon.version {
+                        Some(ref version) => files.iter()
+                            .find(|it| &it.display_name == version && it.is_for(flavor))
+                            .ok_or(format!(
+                                "version {} of {}/{} not found for {}",
+                                version, self.provider, self.addon.name, flavor.name()
+                            ))?,
+                        _ => latest_file(&files, channel, flavor)
+                            .ok_or(format!(
+                                "no {:?} files for {}/{} on {}",
+                                channel, self.provider, self.addon.name, flavor.name()
+                            ))?,
                     };
 
-                    let pending = self.client.get(&url).send()
-                        .and_then(|res| res.into_body().concat2())
-                        .map_err(|err| format!("{}", err));
+                    let result = AddonLock {
+                        name: format!("{}/{}", self.provider, self.addon.name),
+                        resolved: file.id.to_string(),
+                        version: file.display_name.clone(),
+                        timestamp: file.timestamp().unwrap_or(0),
+                        url: Some(file.download_url.clone()),
+                        channel: Some(channel),
+                        ..Default::default()
+                    };
 
-                    Downloading(Box::new(pending))
+                    return Ok(Async::Ready((self.addon.clone(), result)));
                 },
-                Downloading(ref mut f) => {
-                    let body = try_ready!(f.poll());
-                    let files_page = String::from_utf8(body.to_vec()).unwrap();
+            };
 
-                    let doc = Document::from(files_page.as_str());
+            self.inner = next;
+        }
+    }
+}
 
-                    let (version, timestamp) = doc.find(Class("project-file-list-item"))
-                        .map(|version_item| {
-                            let version_name = version_item.find(
-                                Class("project-file-name").descendant(Attr("data-action", "file-link"))
-                            ).next().unwrap().text();
+pub struct CurseSearchFuture {
+    inner: SearchInner,
+    client: Client,
+    provider: &'static str,
+    url: String,
+}
 
-                            let uploaded_abbr = version_item.find(
-                                Class("project-file-date-uploaded").descendant(Name("abbr"))
-                            ).next().unwrap();
+enum SearchInner {
+    Idle,
+    Searching(Box<Future<Item = Vec<Project>, Error = String> + Send>),
+}
 
-                            let uploaded_epoch = uploaded_abbr.attr("data-epoch").unwrap();
-                            (String::from(version_name), uploaded_epoch.parse::<u64>().unwrap())
-                        })
-                        .max_by_key(|item| item.1).unwrap();
+fn search(provider: &'static str, url: String) -> CurseSearchFuture {
+    CurseSearchFuture {
+        inner: SearchInner::Idle,
+        client: Client::new(),
+        provider,
+        url,
+    }
+}
 
-                    let result = AddonLock {
-                        // for curse, addon name and resolved are the same since they have
-                        // proper unique identifiers
-                        name: format!("{}/{}", self.addon.provider, self.addon.name),
-                        resolved: self.addon.name.clone(),
-                        version, timestamp,
-                    };
+impl Future for CurseSearchFuture {
+    type Item = Vec<SearchResult>;
+    type Error = String;
 
-                    return Ok(Async::Ready((self.addon.clone(), result)));
+    fn poll(&mut self) -> Result<Async<Vec<SearchResult>>, String> {
+        use self::SearchInner::*;
+
+        loop {
+            let next = match self.inner {
+                Idle => Searching(get_json(&self.client, &self.url)),
+                Searching(ref mut f) => {
+                    let projects = try_ready!(f.poll());
+
+                    let results = projects.into_iter()
+                        .map(|project| {
+                            let latest = latest_file(&project.latest_files, Channel::Release, Flavor::Retail);
+
+                            SearchResult {
+                                name: format!("{}/{}", self.provider, project.slug),
+                                title: project.name.clone(),
+                                version: latest.map(|it| it.display_name.clone()),
+                                timestamp: latest.and_then(|it| it.timestamp()),
+                            }
+                        })
+                        .collect();
+
+                    return Ok(Async::Ready(results));
                 },
             };
 
@@ -173,3 +388,56 @@ impl Future for CurseLockFuture {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::serde_json;
+
+    const FILES: &'static str = r#"[
+        {"id": 1, "displayName": "1.0", "fileDate": "2019-01-01T00:00:00Z", "releaseType": 1,
+         "downloadUrl": "https://example.com/1", "gameVersionFlavor": null},
+        {"id": 2, "displayName": "1.1", "fileDate": "2019-02-01T00:00:00Z", "releaseType": 1,
+         "downloadUrl": "https://example.com/2", "gameVersionFlavor": "wow_retail"},
+        {"id": 3, "displayName": "1.2-beta", "fileDate": "2019-03-01T00:00:00Z", "releaseType": 2,
+         "downloadUrl": "https://example.com/3", "gameVersionFlavor": "wow_retail"},
+        {"id": 4, "displayName": "1.3-alpha", "fileDate": "2019-04-01T00:00:00Z", "releaseType": 3,
+         "downloadUrl": "https://example.com/4", "gameVersionFlavor": "wow_retail"},
+        {"id": 5, "displayName": "1.1-era", "fileDate": "2019-02-01T00:00:00Z", "releaseType": 1,
+         "downloadUrl": "https://example.com/5", "gameVersionFlavor": "wow_classic"},
+        {"id": 6, "displayName": "1.1-cata", "fileDate": "2019-01-15T00:00:00Z", "releaseType": 1,
+         "downloadUrl": "https://example.com/6", "gameVersionFlavor": "wow_cata"},
+        {"id": 7, "displayName": "1.4-wrath", "fileDate": "2019-05-01T00:00:00Z", "releaseType": 1,
+         "downloadUrl": "https://example.com/7", "gameVersionFlavor": "wow_wrath_classic"},
+        {"id": 8, "displayName": "broken", "fileDate": "yesterday", "releaseType": 1,
+         "downloadUrl": "https://example.com/8", "gameVersionFlavor": "wow_retail"}
+    ]"#;
+
+    fn latest(channel: Channel, flavor: Flavor) -> Option<u64> {
+        let files = serde_json::from_str::<Vec<ProjectFile>>(FILES).unwrap();
+        latest_file(&files, channel, flavor).map(|it| it.id)
+    }
+
+    #[test]
+    fn picks_newest_file_for_channel() {
+        assert_eq!(latest(Channel::Release, Flavor::Retail), Some(2));
+        assert_eq!(latest(Channel::Beta, Flavor::Retail), Some(3));
+        assert_eq!(latest(Channel::Alpha, Flavor::Retail), Some(4));
+        assert_eq!(latest(Channel::Release, Flavor::Ptr), Some(2));
+    }
+
+    #[test]
+    fn picks_file_for_running_flavor_only() {
+        assert_eq!(latest(Channel::Release, Flavor::ClassicEra), Some(5));
+        // the newer wrath build is for a progression server that's gone
+        assert_eq!(latest(Channel::Release, Flavor::Classic), Some(6));
+        assert_eq!(latest(Channel::Release, Flavor::Beta), Some(2));
+    }
+
+    #[test]
+    fn finds_nothing_without_files_for_flavor() {
+        let files = serde_json::from_str::<Vec<ProjectFile>>(&FILES.replace("wow_cata", "wow_classic")).unwrap();
+
+        assert!(latest_file(&files, Channel::Release, Flavor::Classic).is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use ::{Addon, AddonLock};
+use ::toc;
+use super::{Provider, ProviderMetadata};
+use super::{LockFuture, DownloadFuture};
+use super::now;
+use ::futures::future;
+use ::std::fs;
+use ::std::path::{Path, PathBuf};
+use ::std::process::Command;
+
+/// Provider for addons that are only available as git repositories. The
+/// repository is set with `url` in the addon config, optionally together
+/// with a `branch`, `tag` or `rev` to check out and a `path` if the addon
+/// lives in a subdirectory. The addon is installed into a folder named like
+/// that subdirectory, or like its toc if it lives in the repository root.
+/// An exact `version` is treated as a tag.
+///
+/// This shells out to the `git` binary, so any url that git understands
+/// works, including local `file://` repositories.
+pub struct GitProvider;
+
+impl Provider for GitProvider {
+    fn metadata(&self) -> ProviderMetadata {
+        ProviderMetadata {
+            title: String::from("git"),
+            homepage: String::from("https://git-scm.com"),
+        }
+    }
+
+    fn get_lock(&self, addon: Addon, old_lock: Option<AddonLock>) -> LockFuture {
+        Box::new(future::lazy(move || {
+            let lock = resolve(&addon, old_lock)?;
+            Ok((addon, lock))
+        }))
+    }
+
+    fn download_addon(&self, addon: Addon, lock: AddonLock) -> DownloadFuture {
+        Box::new(future::lazy(move || {
+            let path = checkout(&addon, &lock)?;
+            Ok((path, lock))
+        }))
+    }
+}
+
+fn git(args: &[&str], dir: Option<&Path>) -> Result<String, String> {
+    let mut command = Command::new("git");
+    command.args(args);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+
+    let output = command.output()
+        .map_err(|err| format!("could not run git: {}", err))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args[0], String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn repo_url(addon: &Addon) -> Result<&str, String> {
+    addon.url.as_ref()
+        .map(|it| it.as_str())
+        .ok_or(format!("no url set for git/{}", addon.name))
+}
+
+/// Resolves the commit that the addon's branch, tag or rev points to.
+fn resolve(addon: &Addon, old_lock: Option<AddonLock>) -> Result<AddonLock, String> {
+    let url = repo_url(addon)?;
+    let tag = addon.tag.as_ref().or(addon.version.as_ref());
+
+    let (resolved, label) = if let Some(ref rev) = addon.rev {
+        (resolve_rev(addon, url, rev)?, None)
+    } else {
+        let (refs, label) = match (tag, &addon.branch) {
+            (Some(tag), _) => (
+                // annotated tags have to be peeled to get the commit
+                vec![format!("refs/tags/{}^{{}}", tag), format!("refs/tags/{}", tag)],
+                Some(tag.clone()),
+            ),
+            (_, &Some(ref branch)) => (vec![format!("refs/heads/{}", branch)], Some(branch.clone())),
+            _ => (vec![String::from("HEAD")], None),
+        };
+
+        let mut args = vec!["ls-remote", url];
+        args.extend(refs.iter().map(|it| it.as_str()));
+        let output = git(&args, None)?;
+
+        // prefer the peeled tag if there is one, since that's the commit
+        let lines = output.lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                Some((String::from(parts.next()?), String::from(parts.next()?)))
+            })
+            .collect::<Vec<(String, String)>>();
+
+        let hash = refs.iter()
+            .filter_map(|r| lines.iter().find(|(_, name)| name == r))
+            .map(|(hash, _)| hash.clone())
+            .next()
+            .ok_or(format!("could not resolve {} in {}", refs[refs.len() - 1], url))?;
+
+        (hash, label)
+    };
+
+    let short = &resolved[..resolved.len().min(7)];
+    let version = match (tag, label) {
+        (Some(tag), _) => tag.clone(),
+        (_, Some(branch)) => format!("{}@{}", branch, short),
+        _ => String::from(short),
+    };
+
+    // ls-remote doesn't tell us when a commit was made, so the timestamp is
+    // the time we first saw it. this keeps it stable while nothing changes
+    let timestamp = match old_lock {
+        Some(ref old) if old.resolved == resolved => old.timestamp,
+        _ => now(),
+    };
+
+    Ok(AddonLock {
+        name: format!("git/{}", addon.name),
+        resolved, version, timestamp,
+        url: Some(String::from(url)),
+        ..Default::default()
+    })
+}
+
+/// Resolves a rev to the full hash of the commit it points to. Anything but a
+/// full hash, like an abbreviated one or `main~2`, needs a clone for that.
+fn resolve_rev(addon: &Addon, url: &str, rev: &str) -> Result<String, String> {
+    if rev.len() == 40 && rev.chars().all(|it| it.is_digit(16)) {
+        return Ok(rev.to_lowercase());
+    }
+
+    let clone_dir = Path::new(".wam-temp").join(format!("git-rev-{}", addon.name));
+    if clone_dir.exists() {
+        fs::remove_dir_all(&clone_dir).map_err(|err| format!("{}", err))?;
+    }
+
+    let clone_str = clone_dir.to_string_lossy().into_owned();
+    git(&["clone", "--quiet", "--bare", url, &clone_str], None)?;
+
+    let commit = git(
+        &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)],
+        Some(&clone_dir)
+    ).map_err(|_| format!("could not resolve {} in {}", rev, url))?;
+
+    let _ = fs::remove_dir_all(&clone_dir);
+
+    Ok(String::from(commit.trim()))
+}
+
+/// Clones the repository, checks out the locked commit and moves the
+/// addon into its own folder, so it can be installed like an extracted zip.
+fn checkout(addon: &Addon, lock: &AddonLock) -> Result<PathBuf, String> {
+    let url = repo_url(addon)?;
+
+    let base = Path::new(".wam-temp").join(format!("git-{}", addon.name));
+    let clone_dir = base.join("clone");
+    let install_dir = base.join("install");
+
+    let clone_str = clone_dir.to_string_lossy().into_owned();
+    git(&["clone", "--quiet", "--no-checkout", url, &clone_str], None)?;
+    git(&["checkout", "--quiet", &lock.resolved], Some(&clone_dir))?;
+
+    fs::remove_dir_all(clone_dir.join(".git"))
+        .map_err(|err| format!("{}", err))?;
+
+    let source = match addon.path {
+        Some(ref path) => clone_dir.join(path),
+        _ => clone_dir.clone(),
+    };
+
+    if !source.is_dir() {
+        return Err(format!("{} does not exist in {}", source.display(), url));
+    }
+
+    fs::create_dir_all(&install_dir).map_err(|err| format!("{}", err))?;
+    fs::rename(&source, install_dir.join(folder_name(addon, &source)))
+        .map_err(|err| format!("{}", err))?;
+
+    Ok(install_dir)
+}
+
+/// The folder the addon has to be installed into for the client to load it.
+/// The client only loads tocs named like their folder, so addons in the
+/// repository root are named after their toc.
+fn folder_name(addon: &Addon, source: &Path) -> String {
+    if let Some(name) = addon.path.as_ref().and_then(|it| Path::new(it).file_name()) {
+        return name.to_string_lossy().into_owned();
+    }
+
+    let mut names = fs::read_dir(source).into_iter()
+        .flat_map(|it| it)
+        .filter_map(|it| it.ok())
+        .filter_map(|it| toc::folder_name(&it.path()))
+        .collect::<Vec<String>>();
+    names.sort();
+
+    names.into_iter().next().unwrap_or(addon.name.clone())
+}
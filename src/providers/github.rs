@@ -0,0 +1,390 @@
+use super::chrono::prelude::*;
+
+use ::{Addon, AddonLock, Channel, Flavor};
+use ::glob;
+use super::{Provider, ProviderMetadata};
+use super::{LockFuture, DownloadFuture};
+use ::futures::{Future, Async, Stream};
+use ::std::env;
+use ::std::path::{Path, PathBuf};
+use ::std::fs::File;
+use ::std::io::Write;
+
+use ::reqwest::async::{Client, Chunk};
+use ::reqwest::header::{ACCEPT, USER_AGENT};
+
+const DEFAULT_API_URL: &'static str = "https://api.github.com";
+
+// can be set to point the provider at a different api, e.g. a local server
+// that serves release fixtures
+const API_URL_ENV: &'static str = "WAM_GITHUB_API_URL";
+
+const LATEST_RELEASE_URL_TEMPLATE: &'static str = "{api}/repos/{repo}/releases/latest";
+
+// the latest release endpoint never returns prereleases, so the full list is
+// used if the addon wants them
+const RELEASES_URL_TEMPLATE: &'static str = "{api}/repos/{repo}/releases";
+
+const RELEASE_BY_TAG_URL_TEMPLATE: &'static str = "{api}/repos/{repo}/releases/tags/{tag}";
+
+// the github api rejects requests without a user agent
+const WAM_USER_AGENT: &'static str = "wam";
+
+/// Provider for addons that publish zips as assets of their github
+/// releases. Addons are named after their repository, as in
+/// `github/<owner>/<repo>`.
+pub struct GithubProvider {
+    api_url: String,
+}
+
+impl GithubProvider {
+    /// Creates a provider using the api url from `WAM_GITHUB_API_URL`, or
+    /// the public github api if it's not set.
+    pub fn new() -> GithubProvider {
+        let api_url = env::var(API_URL_ENV)
+            .unwrap_or(String::from(DEFAULT_API_URL));
+
+        GithubProvider::with_api_url(&api_url)
+    }
+
+    pub fn with_api_url(api_url: &str) -> GithubProvider {
+        GithubProvider {
+            api_url: String::from(api_url.trim_end_matches('/')),
+        }
+    }
+}
+
+impl Default for GithubProvider {
+    fn default() -> GithubProvider {
+        GithubProvider::new()
+    }
+}
+
+impl Provider for GithubProvider {
+    fn metadata(&self) -> ProviderMetadata {
+        ProviderMetadata {
+            title: String::from("github"),
+            homepage: String::from("https://github.com"),
+        }
+    }
+
+    fn get_lock(&self, addon: Addon, _old_lock: Option<AddonLock>) -> LockFuture {
+        let template = match (&addon.version, addon.channel.unwrap_or_default()) {
+            (&Some(_), _) => RELEASE_BY_TAG_URL_TEMPLATE,
+            (_, Channel::Release) => LATEST_RELEASE_URL_TEMPLATE,
+            _ => RELEASES_URL_TEMPLATE,
+        };
+
+        let url = template
+            .replace("{api}", &self.api_url)
+            .replace("{repo}", &addon.name)
+            .replace("{tag}", addon.version.as_ref().map(|it| it.as_str()).unwrap_or(""));
+
+        Box::new(GithubLockFuture {
+            inner: LockInner::Idle,
+            client: Client::new(),
+            addon, url,
+        })
+    }
+
+    fn download_addon(&self, _addon: Addon, lock: AddonLock) -> DownloadFuture {
+        Box::new(GithubDownloadFuture {
+            inner: DownloadInner::Idle,
+            client: Client::new(),
+            lock,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Release {
+    tag_name: String,
+    // drafts haven't been published yet
+    published_at: Option<String>,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    assets: Vec<Asset>,
+}
+
+impl Release {
+    fn timestamp(&self) -> Option<u64> {
+        self.published_at.as_ref()
+            .and_then(|it| DateTime::parse_from_rfc3339(it).ok())
+            .map(|it| it.timestamp() as u64)
+    }
+
+    // github only distinguishes between releases and prereleases, so any
+    // prerelease counts as a beta
+    fn channel(&self) -> Channel {
+        if self.prerelease { Channel::Beta } else { Channel::Release }
+    }
+}
+
+// the releases endpoint returns a list, the latest release endpoint only one
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Releases {
+    Many(Vec<Release>),
+    One(Release),
+}
+
+#[derive(Deserialize, Debug)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Picks the asset to install from a release. If the addon specifies an
+/// asset pattern, the first matching asset is used. Otherwise, the first
+/// zip for the addon's flavor is used, preferring ones that bundle their
+/// libraries.
+fn pick_asset<'a>(addon: &Addon, assets: &'a Vec<Asset>) -> Option<&'a Asset> {
+    if let Some(ref pattern) = addon.asset {
+        return assets.iter().find(|it| glob::matches(pattern, &it.name));
+    }
+
+    let zips = assets.iter()
+        .filter(|it| it.name.to_lowercase().ends_with(".zip"))
+        .collect::<Vec<&Asset>>();
+
+    // zips without a flavor in their name are the retail build if there are
+    // builds for other flavors, otherwise they're all there is
+    let flavor = addon.flavor.unwrap_or_default().base();
+    let has_flavors = zips.iter().any(|it| Flavor::from_file_name(&it.name).is_some());
+    let zips = zips.into_iter()
+        .filter(|it| match Flavor::from_file_name(&it.name) {
+            Some(zip_flavor) => zip_flavor == flavor,
+            _ => !has_flavors || flavor == Flavor::Retail,
+        })
+        .collect::<Vec<&Asset>>();
+
+    zips.iter()
+        .find(|it| !it.name.to_lowercase().contains("nolib"))
+        .or(zips.first())
+        .map(|it| *it)
+}
+
+struct GithubLockFuture {
+    inner: LockInner,
+    client: Client,
+    addon: Addon,
+    url: String,
+}
+
+enum LockInner {
+    Idle,
+    Resolving(Box<Future<Item = Releases, Error = String> + Send>),
+}
+
+impl Future for GithubLockFuture {
+    type Item = (Addon, AddonLock);
+    type Error = String;
+
+    fn poll(&mut self) -> Result<Async<(Addon, AddonLock)>, String> {
+        use self::LockInner::*;
+
+        loop {
+            let next = match self.inner {
+                Idle => {
+                    let repo = self.addon.name.clone();
+                    let pending = self.client.get(&self.url)
+                        .header(USER_AGENT, WAM_USER_AGENT)
+                        .header(ACCEPT, "application/vnd.github.v3+json")
+                        .send()
+                        .map_err(|err| format!("{}", err))
+                        .and_then(move |res| {
+                            if !res.status().is_success() {
+                                return Err(format!(
+                                    "no release found for {} ({})", repo, res.status()
+                                ));
+                            }
+
+                            Ok(res)
+                        })
+                        .and_then(|mut res| res.json::<Releases>()
+                            .map_err(|err| format!("{}", err)));
+
+                    Resolving(Box::new(pending))
+                },
+                Resolving(ref mut f) => {
+                    let releases = match try_ready!(f.poll()) {
+                        Releases::Many(releases) => releases,
+                        Releases::One(release) => vec![release],
+                    };
+
+                    let channel = self.addon.channel.unwrap_or_default();
+                    let release = releases.iter()
+                        // a release that was asked for by tag is used regardless of channel
+                        .filter(|it| self.addon.version.is_some() || channel.allows(it.channel()))
+                        .filter(|it| !it.draft)
+                        .filter(|it| it.timestamp().is_some())
+                        .max_by_key(|it| it.timestamp())
+                        .ok_or(format!("no {:?} release found for {}", channel, self.addon.name))?;
+
+                    let asset = pick_asset(&self.addon, &release.assets)
+                        .ok_or(format!(
+                            "release {} of {} has no matching zip asset for {}",
+                            release.tag_name, self.addon.name,
+                            self.addon.flavor.unwrap_or_default().name()
+                        ))?;
+
+                    let result = AddonLock {
+                        name: format!("github/{}", self.addon.name),
+                        resolved: release.tag_name.clone(),
+                        version: release.tag_name.clone(),
+                        timestamp: release.timestamp().unwrap_or(0),
+                        url: Some(asset.browser_download_url.clone()),
+                        channel: Some(channel),
+                        ..Default::default()
+                    };
+
+                    return Ok(Async::Ready((self.addon.clone(), result)));
+                },
+            };
+
+            self.inner = next;
+        }
+    }
+}
+
+struct GithubDownloadFuture {
+    inner: DownloadInner,
+    client: Client,
+    lock: AddonLock,
+}
+
+enum DownloadInner {
+    Idle,
+    Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
+}
+
+impl Future for GithubDownloadFuture {
+    type Item = (PathBuf, AddonLock);
+    type Error = String;
+
+    fn poll(&mut self) -> Result<Async<(PathBuf, AddonLock)>, String> {
+        use self::DownloadInner::*;
+
+        loop {
+            let next = match self.inner {
+                Idle => {
+                    let url = self.lock.url.clone()
+                        .ok_or(format!("no download url in lock for {}", self.lock.name))?;
+
+                    let pending = self.client.get(&url)
+                        .header(USER_AGENT, WAM_USER_AGENT)
+                        .send()
+                        .and_then(|res| res.error_for_status())
+                        .and_then(|res| res.into_body().concat2())
+                        .map_err(|err| format!("{}", err));
+
+                    Downloading(Box::new(pending))
+                },
+                Downloading(ref mut f) => {
+                    let body = try_ready!(f.poll());
+
+                    // asset names aren't unique across repositories, so prefix
+                    // them with the addon name to avoid collisions in the temp dir
+                    let asset_name = self.lock.url.as_ref()
+                        .and_then(|it| it.split("/").last())
+                        .unwrap_or("release.zip");
+                    let filename = format!("{}-{}", self.lock.name.replace("/", "-"), asset_name);
+
+                    let filepath = Path::new(".wam-temp").join(&filename);
+                    File::create(&filepath)
+                        .and_then(|mut file| file.write_all(&body))
+                        .map_err(|err| format!("could not write {}: {}", filepath.display(), err))?;
+
+                    return Ok(Async::Ready((filepath, self.lock.clone())));
+                },
+            };
+
+            self.inner = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::serde_json;
+
+    const RELEASE: &'static str = r#"{
+        "tag_name": "v1.0",
+        "published_at": "2019-01-02T03:04:05Z",
+        "assets": [
+            {"name": "MyAddon-1.0.zip.sha256", "browser_download_url": "https://example.com/sha256"},
+            {"name": "MyAddon-1.0-nolib.zip", "browser_download_url": "https://example.com/nolib"},
+            {"name": "MyAddon-1.0.zip", "browser_download_url": "https://example.com/retail"},
+            {"name": "MyAddon-1.0-classic.zip", "browser_download_url": "https://example.com/era"},
+            {"name": "MyAddon-1.0-cata.zip", "browser_download_url": "https://example.com/cata"}
+        ]
+    }"#;
+
+    fn assets(json: &str) -> Vec<Asset> {
+        serde_json::from_str::<Release>(json).unwrap().assets
+    }
+
+    fn picked(addon: &Addon, assets: &Vec<Asset>) -> Option<String> {
+        pick_asset(addon, assets).map(|it| it.name.clone())
+    }
+
+    fn addon(flavor: Flavor) -> Addon {
+        let mut addon = Addon::new("github", "me/myaddon");
+        addon.flavor = Some(flavor);
+        addon
+    }
+
+    #[test]
+    fn picks_zip_for_flavor() {
+        let assets = assets(RELEASE);
+
+        assert_eq!(picked(&addon(Flavor::Retail), &assets), Some(String::from("MyAddon-1.0.zip")));
+        assert_eq!(picked(&addon(Flavor::Ptr), &assets), Some(String::from("MyAddon-1.0.zip")));
+        assert_eq!(picked(&addon(Flavor::ClassicEra), &assets), Some(String::from("MyAddon-1.0-classic.zip")));
+        assert_eq!(picked(&addon(Flavor::Classic), &assets), Some(String::from("MyAddon-1.0-cata.zip")));
+    }
+
+    #[test]
+    fn picks_asset_matching_pattern() {
+        let assets = assets(RELEASE);
+        let mut addon = addon(Flavor::Retail);
+
+        addon.asset = Some(String::from("*-nolib.zip"));
+        assert_eq!(picked(&addon, &assets), Some(String::from("MyAddon-1.0-nolib.zip")));
+
+        addon.asset = Some(String::from("*-wrath.zip"));
+        assert_eq!(picked(&addon, &assets), None);
+    }
+
+    #[test]
+    fn uses_only_zip_for_every_flavor() {
+        let assets = assets(r#"{
+            "tag_name": "v1.0",
+            "published_at": "2019-01-02T03:04:05Z",
+            "assets": [
+                {"name": "MyAddon-1.0-nolib.zip", "browser_download_url": "https://example.com/nolib"},
+                {"name": "MyAddon-1.0.tar.gz", "browser_download_url": "https://example.com/tar"}
+            ]
+        }"#);
+
+        assert_eq!(picked(&addon(Flavor::Retail), &assets), Some(String::from("MyAddon-1.0-nolib.zip")));
+        assert_eq!(picked(&addon(Flavor::Classic), &assets), Some(String::from("MyAddon-1.0-nolib.zip")));
+    }
+
+    #[test]
+    fn finds_nothing_without_zips_for_flavor() {
+        let assets = assets(r#"{
+            "tag_name": "v1.0",
+            "published_at": null,
+            "assets": [
+                {"name": "MyAddon-1.0-mainline.zip", "browser_download_url": "https://example.com/retail"},
+                {"name": "Source code.tar.gz", "browser_download_url": "https://example.com/tar"}
+            ]
+        }"#);
+
+        assert_eq!(picked(&addon(Flavor::ClassicEra), &assets), None);
+    }
+}
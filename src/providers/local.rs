@@ -0,0 +1,117 @@
+use ::{Addon, AddonLock};
+use ::extract;
+use ::hash;
+use super::{Provider, ProviderMetadata};
+use super::{LockFuture, DownloadFuture};
+use super::now;
+use ::futures::future;
+use ::std::fs;
+use ::std::path::{Path, PathBuf};
+
+/// Provider for addons that live on disk, e.g. in-house addons that aren't
+/// published anywhere. `path` in the addon config points to either a zip,
+/// a single addon folder or a folder containing multiple addon folders.
+///
+/// Since there is no release date to go by, the lock stores a hash of the
+/// content and the addon is reinstalled whenever it changes.
+pub struct LocalProvider;
+
+impl Provider for LocalProvider {
+    fn metadata(&self) -> ProviderMetadata {
+        ProviderMetadata {
+            title: String::from("local"),
+            homepage: String::new(),
+        }
+    }
+
+    fn get_lock(&self, addon: Addon, old_lock: Option<AddonLock>) -> LockFuture {
+        Box::new(future::lazy(move || {
+            let lock = resolve(&addon, old_lock)?;
+            Ok((addon, lock))
+        }))
+    }
+
+    fn download_addon(&self, addon: Addon, lock: AddonLock) -> DownloadFuture {
+        Box::new(future::lazy(move || {
+            let content_hash = hash_source(&source_path(&addon)?)?;
+            if lock.hash.is_some() && lock.hash != Some(content_hash) {
+                return Err(format!("{} has changed since it was locked", lock.name));
+            }
+
+            let path = stage(&addon)?;
+            Ok((path, lock))
+        }))
+    }
+
+    fn is_reproducible(&self, lock: &AddonLock) -> bool {
+        lock.hash.is_some()
+    }
+}
+
+fn source_path(addon: &Addon) -> Result<PathBuf, String> {
+    let path = addon.path.as_ref()
+        .map(PathBuf::from)
+        .ok_or(format!("no path set for local/{}", addon.name))?;
+
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()));
+    }
+
+    Ok(path)
+}
+
+/// Checks whether a directory is an addon folder itself, rather than a
+/// folder containing addons.
+fn is_addon_folder(path: &Path) -> bool {
+    fs::read_dir(path)
+        .map(|entries| entries.filter_map(|it| it.ok()).any(|it| {
+            it.file_name().to_string_lossy().to_lowercase().ends_with(".toc")
+        }))
+        .unwrap_or(false)
+}
+
+fn hash_source(path: &Path) -> Result<String, String> {
+    if path.is_dir() {
+        hash::hash_dir(path)
+    } else {
+        hash::hash_file(path)
+    }.map_err(|err| format!("could not read {}: {}", path.display(), err))
+}
+
+fn resolve(addon: &Addon, old_lock: Option<AddonLock>) -> Result<AddonLock, String> {
+    let path = source_path(addon)?;
+    let content_hash = hash_source(&path)?;
+
+    let timestamp = match old_lock {
+        Some(ref old) if old.hash.as_ref() == Some(&content_hash) => old.timestamp,
+        _ => now(),
+    };
+
+    Ok(AddonLock {
+        name: format!("local/{}", addon.name),
+        resolved: path.to_string_lossy().into_owned(),
+        version: String::from(&content_hash[..8]),
+        timestamp,
+        hash: Some(content_hash),
+        ..Default::default()
+    })
+}
+
+/// Returns something that can be extracted into the addon directory: zips
+/// and folders of addons are used as is, single addon folders are copied
+/// into the temp dir so they get installed as one folder.
+fn stage(addon: &Addon) -> Result<PathBuf, String> {
+    let path = source_path(addon)?;
+    if !path.is_dir() || !is_addon_folder(&path) {
+        return Ok(path);
+    }
+
+    let folder_name = path.file_name()
+        .ok_or(format!("{} has no folder name", path.display()))?;
+
+    let staged = Path::new(".wam-temp").join(format!("local-{}", addon.name));
+    extract::copy_dir(&path, &staged.join(folder_name))
+        .map_err(|err| format!("could not copy {}: {}", path.display(), err))?;
+
+    Ok(staged)
+}
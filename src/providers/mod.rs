@@ -1,103 +1,176 @@
-extern crate select;
 extern crate chrono;
 
 mod tuk;
 mod curse;
+mod github;
+mod wowi;
+mod git;
+mod local;
+mod url;
 
 use super::{Addon, AddonLock};
+use ::std::collections::BTreeMap;
 use ::std::path::PathBuf;
+use ::std::time::{SystemTime, UNIX_EPOCH};
+
+use ::futures::Future;
+use ::reqwest::async::Client;
+
+pub use self::tuk::TukProvider;
+pub use self::curse::CurseProvider;
+pub use self::github::GithubProvider;
+pub use self::wowi::WowiProvider;
+pub use self::git::GitProvider;
+pub use self::local::LocalProvider;
+pub use self::url::UrlProvider;
+
+pub type LockFuture = Box<Future<Item = (Addon, AddonLock), Error = String> + Send>;
+pub type DownloadFuture = Box<Future<Item = (PathBuf, AddonLock), Error = String> + Send>;
+pub type SearchFuture = Box<Future<Item = Vec<SearchResult>, Error = String> + Send>;
+
+/// A source that addons can be resolved, downloaded and searched from.
+///
+/// Providers are registered in a `Registry` under the name that is used as
+/// the `provider` field in the config, so adding a new source only requires
+/// implementing this trait and registering it.
+pub trait Provider: Send + Sync {
+    fn metadata(&self) -> ProviderMetadata;
+
+    /// Resolves the newest version of an addon. The previous lock is passed
+    /// in if there is one, so providers can reuse already resolved ids.
+    fn get_lock(&self, addon: Addon, old_lock: Option<AddonLock>) -> LockFuture;
+
+    /// Downloads the version described by `lock` into the temp dir and
+    /// returns the path to the downloaded archive.
+    fn download_addon(&self, addon: Addon, lock: AddonLock) -> DownloadFuture;
+
+    /// Checks whether `lock` records enough to download the exact same files
+    /// again, which is required by `install --locked`. Most providers need a
+    /// download url for that.
+    fn is_reproducible(&self, lock: &AddonLock) -> bool {
+        lock.url.is_some()
+    }
 
-use ::futures::{Future, Async};
+    /// Searches the provider for addons. Providers that can't be searched
+    /// return `None`.
+    fn search(&self, _term: &str) -> Option<SearchFuture> {
+        None
+    }
+}
 
-use self::tuk::{TukDownloadFuture, TukLockFuture};
-use self::curse::{CurseDownloadFuture, CurseLockFuture};
+#[derive(Debug, Clone)]
+pub struct ProviderMetadata {
+    pub title: String,
+    pub homepage: String,
+}
 
-pub struct AddonLockFuture {
-    inner: LockInner,
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    // identifier in the format <provider>/<name>, as used by `wam add`
+    pub name: String,
+    pub title: String,
+    pub version: Option<String>,
+    pub timestamp: Option<u64>,
 }
 
-enum LockInner {
-    CurseLockFuture(CurseLockFuture),
-    TukLockFuture(TukLockFuture),
+pub struct Registry {
+    providers: BTreeMap<String, Box<Provider>>,
 }
 
-impl Future for AddonLockFuture {
-    type Item = (Addon, AddonLock);
-    type Error = String;
+impl Registry {
+    /// Creates a registry without any providers. Use `Registry::with_builtin()`
+    /// to get one with all built-in providers.
+    pub fn new() -> Registry {
+        Registry { providers: BTreeMap::new() }
+    }
 
-    fn poll(&mut self) -> Result<Async<(Addon, AddonLock)>, String> {
-        use self::LockInner::*;
+    /// Creates a registry with all built-in providers, configured from the
+    /// environment.
+    pub fn with_builtin() -> Registry {
+        let mut registry = Registry::new();
+        registry.register("curse", CurseProvider::curse());
+        registry.register("ace", CurseProvider::ace());
+        registry.register("tukui", TukProvider::new());
+        registry.register("github", GithubProvider::new());
+        registry.register("wowi", WowiProvider::new());
+        registry.register("git", GitProvider);
+        registry.register("local", LocalProvider);
+        registry.register("url", UrlProvider);
+
+        registry
+    }
 
-        match self.inner {
-            CurseLockFuture(ref mut f) => f.poll(),
-            TukLockFuture(ref mut f) => f.poll(),
-        }
+    /// Registers a provider under `name`, replacing any provider that was
+    /// previously registered with the same name.
+    pub fn register<P: Provider + 'static>(&mut self, name: &str, provider: P) {
+        self.providers.insert(String::from(name), Box::new(provider));
     }
-}
 
-pub fn get_lock(
-    addon: (Addon, Option<AddonLock>)
-) -> Option<AddonLockFuture> {
-    let (addon, old_lock) = addon;
-
-    match addon.provider.as_str() {
-        "curse" | "ace" => {
-            let inner = LockInner::CurseLockFuture(curse::get_lock(addon));
-            Some(AddonLockFuture { inner })
-        },
-        "tukui" => {
-            let inner = LockInner::TukLockFuture(tuk::get_lock(addon, old_lock));
-            Some(AddonLockFuture { inner })
-        },
-        _ => {
-            println!("skipping unkown provider: {}/{}", addon.name, addon.provider);
-            None
-        },
+    pub fn get(&self, name: &str) -> Option<&Provider> {
+        self.providers.get(name).map(|it| &**it)
     }
-}
 
-pub struct DownloadAddonFuture {
-    inner: DownloadInner,
-}
+    pub fn names(&self) -> Vec<&str> {
+        self.providers.keys().map(|it| it.as_str()).collect()
+    }
 
-enum DownloadInner {
-    CurseDownloadFuture(CurseDownloadFuture),
-    TukDownloadFuture(TukDownloadFuture),
-}
+    pub fn get_lock(
+        &self, addon: (Addon, Option<AddonLock>)
+    ) -> Option<LockFuture> {
+        let (addon, old_lock) = addon;
+
+        match self.get(&addon.provider) {
+            Some(provider) => Some(provider.get_lock(addon, old_lock)),
+            _ => {
+                println!("skipping unkown provider: {}/{}", addon.provider, addon.name);
+                None
+            },
+        }
+    }
 
-impl Future for DownloadAddonFuture {
-    type Item = (PathBuf, AddonLock);
-    type Error = String;
+    pub fn download_addon(
+        &self, addon: (Addon, AddonLock)
+    ) -> Option<DownloadFuture> {
+        let (addon, lock) = addon;
 
-    fn poll(&mut self) -> Result<Async<(PathBuf, AddonLock)>, String> {
-        use self::DownloadInner::*;
+        self.get(&addon.provider)
+            .map(|provider| provider.download_addon(addon, lock))
+    }
 
-        match self.inner {
-            CurseDownloadFuture(ref mut f) => f.poll(),
-            TukDownloadFuture(ref mut f) => f.poll(),
-        }
+    /// Starts a search on every searchable provider, returning the provider
+    /// names alongside their pending results.
+    pub fn search(&self, term: &str) -> Vec<(String, SearchFuture)> {
+        self.providers.iter()
+            .filter_map(|(name, provider)| {
+                provider.search(term).map(|f| (name.clone(), f))
+            })
+            .collect()
     }
 }
 
-pub fn download_addon(
-    addon: (Addon, AddonLock)
-) -> Option<DownloadAddonFuture> {
-    let (addon, lock) = addon;
-
-    let provider = addon.provider.clone();
-    let inner = match provider.as_str() {
-        "curse" | "ace" => {
-            DownloadInner::CurseDownloadFuture(
-                curse::download_addon(addon, lock)
-            )
-        },
-        "tukui" => {
-            DownloadInner::TukDownloadFuture(
-                tuk::download_addon(addon, lock)
-            )
-        }
-        _ => return None,
-    };
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+/// Current unix timestamp, used by providers that can't get a release date
+/// from their source.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs())
+        .unwrap_or(0)
+}
 
-    Some(DownloadAddonFuture { inner })
+/// Fetches `url` and deserializes the json it responds with, failing for
+/// error statuses.
+fn get_json<T>(client: &Client, url: &str) -> Box<Future<Item = T, Error = String> + Send>
+    where T: ::serde::de::DeserializeOwned + Send + 'static
+{
+    let pending = client.get(url).send()
+        .and_then(|res| res.error_for_status())
+        .and_then(|mut res| res.json::<T>())
+        .map_err(|err| format!("{}", err));
+
+    Box::new(pending)
 }
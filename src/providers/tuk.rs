@@ -1,226 +1,175 @@
-use super::select::predicate::*;
-use super::select::document::Document;
 use super::chrono::prelude::*;
 
-use ::{Addon, AddonLock};
+use ::{Addon, AddonLock, Flavor};
+use super::{Provider, ProviderMetadata, SearchResult};
+use super::{LockFuture, DownloadFuture, SearchFuture};
+use super::get_json;
 use ::futures::{Future, Async, Stream};
+use ::std::env;
+use ::std::fmt;
 use ::std::path::{Path, PathBuf};
 use ::std::fs::File;
 use ::std::io::Write;
 
-use ::reqwest::async::{Response, Client, Chunk};
+use ::reqwest::async::{Client, Chunk};
 
+// only used for locks that were created before download urls were stored
 pub const ADDON_DL_URL_TEMPLATE: &'static str =
     "https://www.tukui.org/addons.php?download={}";
 
-const UI_DL_URL_TEMPLATE: &'static str =
-    "https://www.tukui.org/download.php?ui={}";
+const DEFAULT_API_URL: &'static str = "https://www.tukui.org/client-api.php";
+const API_URL_ENV: &'static str = "WAM_TUKUI_API_URL";
 
-const SEARCH_URL_TEMPLATE: &'static str =
-    "https://www.tukui.org/addons.php?search={}";
+const UI_URL_TEMPLATE: &'static str = "{api}?ui={}";
+const ADDONS_URL: &'static str = "{api}?addons=all";
 
-const ADDON_URL_TEMPLATE: &'static str =
-    "https://www.tukui.org/addons.php?id={}";
+// classic builds, including the ones of the interfaces, have their own lists
+const CLASSIC_ERA_ADDONS_URL: &'static str = "{api}?classic-addons=all";
+const CLASSIC_ADDONS_URL: &'static str = "{api}?classic-wotlk-addons=all";
 
-const HOME_URL: &'static str = "https://www.tukui.org/welcome.php";
-const BASE_URL_TEMPLATE: &'static str = "https://www.tukui.org{}";
-
-pub struct TukDownloadFuture {
-    inner: DownloadInner,
+/// Provider for tukui.org, which hosts both the tukui and elvui interfaces
+/// as well as a number of standalone addons. The interfaces are referred to
+/// by name, addons by their id or their name. Classic addons have separate
+/// ids, so the name is the better choice for addons shared between targets.
+pub struct TukProvider {
+    api_url: String,
 }
 
-pub fn download_addon(addon: Addon, lock: AddonLock) -> TukDownloadFuture {
-    let name = addon.name.clone();
-    let client = Client::new();
-
-    let inner = match name.as_str() {
-        "tukui" | "elvui" => DownloadInner::HomeDownloadFuture(HomeDownloadFuture {
-            lock, addon, client,
-            inner: HomeDownloadInner::Idle,
-            filename: None,
-        }),
-        _ => DownloadInner::AddonDownloadFuture(AddonDownloadFuture {
-            lock, client,
-            inner: AddonDownloadInner::Idle,
-            filename: None,
-        }),
-    };
-
-    TukDownloadFuture { inner }
-}
+impl TukProvider {
+    pub fn new() -> TukProvider {
+        let api_url = env::var(API_URL_ENV)
+            .unwrap_or(String::from(DEFAULT_API_URL));
 
-impl Future for TukDownloadFuture {
-    type Item = (PathBuf, AddonLock);
-    type Error = String;
-
-    fn poll(&mut self) -> Result<Async<(PathBuf, AddonLock)>, String> {
-        use self::DownloadInner::*;
+        TukProvider::with_api_url(&api_url)
+    }
 
-        match self.inner {
-            HomeDownloadFuture(ref mut f) => f.poll(),
-            AddonDownloadFuture(ref mut f) => f.poll(),
+    pub fn with_api_url(api_url: &str) -> TukProvider {
+        TukProvider {
+            api_url: String::from(api_url),
         }
     }
-}
-
-enum DownloadInner {
-    HomeDownloadFuture(HomeDownloadFuture),
-    AddonDownloadFuture(AddonDownloadFuture),
-}
 
-struct HomeDownloadFuture {
-    inner: HomeDownloadInner,
-    client: Client,
-    lock: AddonLock,
-    filename: Option<String>,
-    addon: Addon,
+    fn fetch(&self, client: &Client, addon_name: &str, flavor: Flavor) -> Box<Future<Item = Vec<TukAddon>, Error = String> + Send> {
+        let list_url = match flavor.base() {
+            Flavor::ClassicEra => CLASSIC_ERA_ADDONS_URL,
+            Flavor::Classic => CLASSIC_ADDONS_URL,
+            _ => ADDONS_URL,
+        };
+
+        match addon_name {
+            // the retail interfaces aren't part of the addon list and have their own endpoint
+            "tukui" | "elvui" if flavor.base() == Flavor::Retail => {
+                let url = UI_URL_TEMPLATE
+                    .replace("{api}", &self.api_url)
+                    .replace("{}", addon_name);
+
+                Box::new(get_json::<TukAddon>(client, &url).map(|it| vec![it]))
+                    as Box<Future<Item = Vec<TukAddon>, Error = String> + Send>
+            },
+            _ => get_json::<Vec<TukAddon>>(client, &list_url.replace("{api}", &self.api_url)),
+        }
+    }
 }
 
-enum HomeDownloadInner {
-    Idle,
-    GettingDownloadLink(Box<Future<Item = Chunk, Error = String> + Send>),
-    Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
+impl Default for TukProvider {
+    fn default() -> TukProvider {
+        TukProvider::new()
+    }
 }
 
-impl Future for HomeDownloadFuture {
-    type Item = (PathBuf, AddonLock);
-    type Error = String;
-
-    fn poll(&mut self) -> Result<Async<(PathBuf, AddonLock)>, String> {
-        use self::HomeDownloadInner::*;
+impl Provider for TukProvider {
+    fn metadata(&self) -> ProviderMetadata {
+        ProviderMetadata {
+            title: String::from("tukui"),
+            homepage: String::from("https://www.tukui.org"),
+        }
+    }
 
-        loop {
-            let next = match self.inner {
-                Idle => {
-                    let pending = self.client.get(HOME_URL).send()
-                        .and_then(|res| res.into_body().concat2())
-                        .map_err(|err| format!("{}", err));
+    fn get_lock(&self, addon: Addon, old_lock: Option<AddonLock>) -> LockFuture {
+        let client = Client::new();
+        let pending = self.fetch(&client, &addon.name, addon.flavor.unwrap_or_default());
 
-                    GettingDownloadLink(Box::new(pending))
-                },
-                GettingDownloadLink(ref mut f) => {
-                    let homepage = try_ready!(f.map_err(|err| format!("{}", err)).poll());
-                    let homepage = String::from_utf8(homepage.to_vec()).unwrap();
-
-                    let doc = Document::from(homepage.as_str());
-                    let dl_start = format!("/downloads/{}", self.addon.name);
-
-                    let mut url = None;
-                    for link in doc.find(Name("a")) {
-                        match link.attr("href") {
-                            Some(href) => {
-                                if href.starts_with(&dl_start) && href.ends_with(".zip") {
-                                    let filename = href.split("/").last().unwrap();
-                                    self.filename = Some(String::from(filename));
-                                    url = Some(BASE_URL_TEMPLATE.replace("{}", &href));
-                                }
-                            },
-                            _ => {},
-                        };
-                    }
-
-                    let pending = self.client.get(&url.unwrap()).send()
-                        .and_then(|res| res.into_body().concat2())
-                        .map_err(|err| format!("{}", err));
+        Box::new(TukLockFuture {
+            inner: pending,
+            resolved: old_lock.map(|it| it.resolved),
+            addon,
+        })
+    }
 
-                    Downloading(Box::new(pending))
-                },
-                Downloading(ref mut f) => {
-                    let body = try_ready!(f.map_err(|err| format!("{}", err)).poll());
-                    let filename = self.filename.take().unwrap();
-                    let filepath = Path::new(&".wam-temp").join(&filename);
-                    let mut file = File::create(&filepath).expect("could not create file");
-                    file.write_all(&body).expect("could not write to file");
+    fn download_addon(&self, _addon: Addon, lock: AddonLock) -> DownloadFuture {
+        Box::new(TukDownloadFuture {
+            inner: DownloadInner::Idle,
+            client: Client::new(),
+            lock,
+        })
+    }
 
-                    return Ok(Async::Ready((filepath, self.lock.clone())));
-                },
-            };
+    fn search(&self, term: &str) -> Option<SearchFuture> {
+        let client = Client::new();
+        let pending = self.fetch(&client, "", Flavor::Retail);
 
-            self.inner = next;
-        }
+        Some(Box::new(TukSearchFuture {
+            inner: pending,
+            term: term.to_lowercase(),
+        }))
     }
 }
 
-struct AddonDownloadFuture {
-    inner: AddonDownloadInner,
-    client: Client,
-    lock: AddonLock,
-    filename: Option<String>,
+// ids are numbers for some endpoints and strings for others
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum TukId {
+    Number(u64),
+    Text(String),
 }
 
-enum AddonDownloadInner {
-    Idle,
-    ReadingFilename(Box<Future<Item = Response, Error = String> + Send>),
-    Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
+impl fmt::Display for TukId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TukId::Number(ref id) => write!(f, "{}", id),
+            TukId::Text(ref id) => write!(f, "{}", id),
+        }
+    }
 }
 
-impl Future for AddonDownloadFuture {
-    type Item = (PathBuf, AddonLock);
-    type Error = String;
-
-    fn poll(&mut self) -> Result<Async<(PathBuf, AddonLock)>, String> {
-        use self::AddonDownloadInner::*;
-
-        loop {
-            let next = match self.inner {
-                Idle => {
-                    let url = ADDON_DL_URL_TEMPLATE.replace("{}", &self.lock.resolved);
-                    let pending = self.client.get(&url).send()
-                        .map_err(|err| format!("{}", err));
-
-                    ReadingFilename(Box::new(pending))
-                },
-                ReadingFilename(ref mut f) => {
-                    let res = try_ready!(f.poll());
-                    let filename = {
-                        let header = res.headers()["content-disposition"].to_str().unwrap();
-                        let filename = header.split("filename=").last().unwrap();
-                        String::from(filename)
-                    };
-
-                    self.filename = Some(filename);
-                    let body = res.into_body().concat2()
-                        .map_err(|err| format!("{}", err));
+#[derive(Deserialize, Debug, Clone)]
+struct TukAddon {
+    id: TukId,
+    name: String,
+    version: String,
+    url: String,
+    lastupdate: String,
+}
 
-                    Downloading(Box::new(body))
-                },
-                Downloading(ref mut f) => {
-                    let body = try_ready!(f.poll());
-                    let filename = self.filename.take().unwrap();
-                    let filepath = Path::new(".wam-temp").join(&filename);
-                    let mut file = File::create(&filepath).expect("could not create file");
-                    file.write_all(&body).expect("could not write to file");
+impl TukAddon {
+    fn timestamp(&self) -> Option<u64> {
+        // the interfaces only have a date, addons have date and time
+        let date = if self.lastupdate.len() == 10 {
+            format!("{} 00:00:00", self.lastupdate)
+        } else {
+            self.lastupdate.clone()
+        };
+
+        Utc.datetime_from_str(&date, "%Y-%m-%d %H:%M:%S").ok()
+            .map(|it| it.timestamp() as u64)
+    }
 
-                    return Ok(Async::Ready((filepath, self.lock.clone())));
-                },
-            };
+    /// Checks whether this is the addon that `name` (or a previously
+    /// resolved id) refers to. Names are matched case insensitively.
+    fn matches(&self, name: &str, resolved: &Option<String>) -> bool {
+        let id = self.id.to_string();
 
-            self.inner = next;
+        match *resolved {
+            Some(ref resolved) => &id == resolved,
+            _ => id == name || self.name.to_lowercase() == name.to_lowercase(),
         }
     }
 }
 
 pub struct TukLockFuture {
-    inner: LockInner,
-}
-
-pub fn get_lock(addon: Addon, old_lock: Option<AddonLock>) -> TukLockFuture {
-    let name = addon.name.clone();
-    let client = Client::new();
-
-    let inner = match name.as_str() {
-        "tukui" | "elvui" => LockInner::HomeLockFuture(HomeLockFuture {
-            inner: HomeLockInner::Idle,
-            client, addon,
-        }),
-        _ => LockInner::AddonLockFuture(AddonLockFuture {
-            inner: AddonLockInner::Idle,
-            resolved: old_lock.and_then(|it| Some(it.resolved)),
-            client, addon,
-        }),
-    };
-
-    TukLockFuture { inner }
+    inner: Box<Future<Item = Vec<TukAddon>, Error = String> + Send>,
+    addon: Addon,
+    resolved: Option<String>,
 }
 
 impl Future for TukLockFuture {
@@ -228,73 +177,87 @@ impl Future for TukLockFuture {
     type Error = String;
 
     fn poll(&mut self) -> Result<Async<(Addon, AddonLock)>, String> {
-        use self::LockInner::*;
-
-        match self.inner {
-            HomeLockFuture(ref mut f) => f.poll(),
-            AddonLockFuture(ref mut f) => f.poll(),
+        let addons = try_ready!(self.inner.poll());
+        let flavor = self.addon.flavor.unwrap_or_default();
+        let is_ui = (self.addon.name == "tukui" || self.addon.name == "elvui")
+            && flavor.base() == Flavor::Retail;
+
+        let found = addons.iter()
+            .find(|it| is_ui || it.matches(&self.addon.name, &self.resolved))
+            .ok_or(match flavor.base() {
+                Flavor::Retail => format!("addon tukui/{} not found", self.addon.name),
+                _ => format!("tukui/{} has no build for {}", self.addon.name, flavor.name()),
+            })?;
+
+        if let Some(ref version) = self.addon.version {
+            if version != &found.version {
+                return Err(format!(
+                    "tukui/{} {} is not available, only the latest version ({}) can be installed",
+                    self.addon.name, version, found.version
+                ));
+            }
         }
-    }
-}
 
-enum LockInner {
-    HomeLockFuture(HomeLockFuture),
-    AddonLockFuture(AddonLockFuture),
+        let result = AddonLock {
+            name: format!("tukui/{}", self.addon.name),
+            resolved: if is_ui {
+                self.addon.name.clone()
+            } else {
+                found.id.to_string()
+            },
+            version: found.version.clone(),
+            timestamp: found.timestamp().unwrap_or(0),
+            url: Some(found.url.clone()),
+            ..Default::default()
+        };
+
+        Ok(Async::Ready((self.addon.clone(), result)))
+    }
 }
 
-struct HomeLockFuture {
-    inner: HomeLockInner,
+pub struct TukDownloadFuture {
+    inner: DownloadInner,
     client: Client,
-    addon: Addon,
+    lock: AddonLock,
 }
 
-enum HomeLockInner {
+enum DownloadInner {
     Idle,
     Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
 }
 
-impl Future for HomeLockFuture {
-    type Item = (Addon, AddonLock);
+impl Future for TukDownloadFuture {
+    type Item = (PathBuf, AddonLock);
     type Error = String;
 
-    fn poll(&mut self) -> Result<Async<(Addon, AddonLock)>, String> {
-        use self::HomeLockInner::*;
+    fn poll(&mut self) -> Result<Async<(PathBuf, AddonLock)>, String> {
+        use self::DownloadInner::*;
 
         loop {
             let next = match self.inner {
                 Idle => {
-                    let url = UI_DL_URL_TEMPLATE.replace("{}", &self.addon.name);
+                    let url = self.lock.url.clone()
+                        .unwrap_or(ADDON_DL_URL_TEMPLATE.replace("{}", &self.lock.resolved));
+
                     let pending = self.client.get(&url).send()
+                        .and_then(|res| res.error_for_status())
                         .and_then(|res| res.into_body().concat2())
                         .map_err(|err| format!("{}", err));
 
                     Downloading(Box::new(pending))
                 },
                 Downloading(ref mut f) => {
-                    let body = try_ready!(f.map_err(|err| format!("{}", err)).poll());
-                    let page = String::from_utf8(body.to_vec()).unwrap();
-                    let doc = Document::from(page.as_str());
-
-                    let mut version_els = doc.find(
-                        Attr("id", "version").descendant(
-                            Name("b").and(Class("Premium"))
-                        )
-                    );
-
-                    let version = version_els.next().unwrap().text();
-                    let date = version_els.next().unwrap().text();
-                    let date = format!("{} 00:00:00", date);
-
-                    let parsed_date = Utc.datetime_from_str(&date, "%Y-%m-%d %H:%M:%S").unwrap();
-                    let timestamp = parsed_date.timestamp() as u64;
-
-                    let result = AddonLock {
-                        name: format!("tukui/{}", self.addon.name),
-                        resolved: self.addon.name.clone(),
-                        version, timestamp,
-                    };
-
-                    return Ok(Async::Ready((self.addon.clone(), result)));
+                    let body = try_ready!(f.poll());
+
+                    // addon download links point to a php script, so the
+                    // filename is built from the resolved id instead
+                    let filename = format!("tukui-{}.zip", self.lock.resolved);
+                    let filepath = Path::new(".wam-temp").join(&filename);
+                    File::create(&filepath)
+                        .and_then(|mut file| file.write_all(&body))
+                        .map_err(|err| format!("could not write {}: {}", filepath.display(), err))?;
+
+                    return Ok(Async::Ready((filepath, self.lock.clone())));
                 },
             };
 
@@ -303,104 +266,29 @@ impl Future for HomeLockFuture {
     }
 }
 
-struct AddonLockFuture {
-    inner: AddonLockInner,
-    addon: Addon,
-    client: Client,
-    resolved: Option<String>,
+pub struct TukSearchFuture {
+    inner: Box<Future<Item = Vec<TukAddon>, Error = String> + Send>,
+    term: String,
 }
 
-enum AddonLockInner {
-    Idle,
-    Resolving(Box<Future<Item = Chunk, Error = String> + Send>),
-    Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
-}
-
-impl Future for AddonLockFuture {
-    type Item = (Addon, AddonLock);
+impl Future for TukSearchFuture {
+    type Item = Vec<SearchResult>;
     type Error = String;
 
-    fn poll(&mut self) -> Result<Async<(Addon, AddonLock)>, String> {
-        use self::AddonLockInner::*;
-
-        loop {
-            let next = match self.inner {
-                Idle => {
-                    if self.resolved.is_some() {
-                        let url = ADDON_DL_URL_TEMPLATE.replace("{}", &self.addon.name);
-                        let pending = self.client.get(&url).send()
-                            .and_then(|res| res.into_body().concat2())
-                            .map_err(|err| format!("{}", err));
-
-                        Downloading(Box::new(pending))
-                    } else {
-                        let search_term = self.addon.name
-                            .replace(" ", "+")
-                            .to_lowercase();
-
-                        let url = SEARCH_URL_TEMPLATE.replace("{}", &search_term);
-                        let pending = self.client.get(&url).send()
-                            .and_then(|res| res.into_body().concat2())
-                            .map_err(|err| format!("{}", err));
-
-                        Resolving(Box::new(pending))
-                    }
-                },
-                Resolving(ref mut f) => {
-                    let body = try_ready!(f.map_err(|err| format!("{}", err)).poll());
-                    let page = String::from_utf8(body.to_vec()).unwrap();
-
-                    let doc = Document::from(page.as_str());
-                    let result_node = doc.find(
-                        Class("addons")
-                            .and(Class("addons-list"))
-                            .descendant(Name("a"))
-                    ).next().unwrap();
-
-                    let href = result_node.attr("href").unwrap();
-                    let resolved = String::from(href.split("?id=").last().unwrap());
-
-                    let addon_url = ADDON_URL_TEMPLATE.replace("{}", &resolved);
-                    let pending = self.client.get(&addon_url).send()
-                        .and_then(|res| res.into_body().concat2())
-                        .map_err(|err| format!("{}", err));
-
-                    self.resolved = Some(resolved);
-
-                    Downloading(Box::new(pending))
-                },
-                Downloading(ref mut f) => {
-                    let body = try_ready!(f.map_err(|err| format!("{}", err)).poll());
-                    let page = String::from_utf8(body.to_vec()).unwrap();
-                    let doc = Document::from(page.as_str());
-
-                    let mut version_els = doc.find(
-                        Attr("id", "extras").descendant(
-                            Name("b").and(Class("VIP"))
-                        )
-                    );
-
-                    // TODO: why is version not there wtf
-                    let version = version_els.next().unwrap().text();
-                    let date = version_els.next().unwrap().text();
-                    let time = version_els.next().unwrap().text();
-
-                    let date_str = format!("{} {}:00", date, time);
-
-                    let parsed_date = Utc.datetime_from_str(&date_str, "%b %e, %Y %H:%M:%S").unwrap();
-                    let timestamp = parsed_date.timestamp() as u64;
-
-                    let result = AddonLock {
-                        name: format!("tukui/{}", self.addon.name),
-                        resolved: self.resolved.take().unwrap(),
-                        version, timestamp,
-                    };
-
-                    return Ok(Async::Ready((self.addon.clone(), result)));
-                },
-            };
-
-            self.inner = next;
-        }
+    fn poll(&mut self) -> Result<Async<Vec<SearchResult>>, String> {
+        let addons = try_ready!(self.inner.poll());
+
+        // ids are used as names here since addon names can contain spaces
+        let results = addons.iter()
+            .filter(|it| it.name.to_lowercase().contains(&self.term))
+            .map(|it| SearchResult {
+                name: format!("tukui/{}", it.id),
+                title: it.name.clone(),
+                version: Some(it.version.clone()),
+                timestamp: it.timestamp(),
+            })
+            .collect();
+
+        Ok(Async::Ready(results))
     }
 }
@@ -0,0 +1,175 @@
+use ::{Addon, AddonLock};
+use ::hash;
+use super::{Provider, ProviderMetadata};
+use super::{LockFuture, DownloadFuture};
+use super::now;
+use ::futures::{Future, Async, Stream};
+use ::std::path::{Path, PathBuf};
+use ::std::fs::File;
+use ::std::io::Write;
+
+use ::reqwest::async::{Client, Chunk};
+
+/// Provider for zips behind an arbitrary http link, set with `url` in the
+/// addon config. Like local addons, these are tracked by a hash of their
+/// content, since there's no way to know when the file behind a link changed.
+pub struct UrlProvider;
+
+impl Provider for UrlProvider {
+    fn metadata(&self) -> ProviderMetadata {
+        ProviderMetadata {
+            title: String::from("url"),
+            homepage: String::new(),
+        }
+    }
+
+    fn get_lock(&self, addon: Addon, old_lock: Option<AddonLock>) -> LockFuture {
+        Box::new(UrlLockFuture {
+            inner: LockInner::Idle,
+            client: Client::new(),
+            addon, old_lock,
+        })
+    }
+
+    fn download_addon(&self, addon: Addon, lock: AddonLock) -> DownloadFuture {
+        Box::new(UrlDownloadFuture {
+            inner: DownloadInner::Idle,
+            client: Client::new(),
+            addon, lock,
+        })
+    }
+
+    fn is_reproducible(&self, lock: &AddonLock) -> bool {
+        lock.hash.is_some()
+    }
+}
+
+// the file is already downloaded for hashing when resolving the lock, so it
+// is kept in the temp dir and reused if the download is still needed
+fn download_path(addon: &Addon) -> PathBuf {
+    Path::new(".wam-temp").join(format!("url-{}.zip", addon.name.replace("/", "-")))
+}
+
+fn fetch(client: &Client, addon: &Addon) -> Result<Box<Future<Item = Chunk, Error = String> + Send>, String> {
+    let url = addon.url.clone()
+        .ok_or(format!("no url set for url/{}", addon.name))?;
+
+    let pending = client.get(&url).send()
+        .and_then(|res| res.error_for_status())
+        .and_then(|res| res.into_body().concat2())
+        .map_err(|err| format!("{}", err));
+
+    Ok(Box::new(pending))
+}
+
+fn write_download(addon: &Addon, body: &Chunk) -> Result<PathBuf, String> {
+    let filepath = download_path(addon);
+    File::create(&filepath)
+        .and_then(|mut file| file.write_all(body))
+        .map_err(|err| format!("could not write {}: {}", filepath.display(), err))?;
+
+    Ok(filepath)
+}
+
+struct UrlLockFuture {
+    inner: LockInner,
+    client: Client,
+    addon: Addon,
+    old_lock: Option<AddonLock>,
+}
+
+enum LockInner {
+    Idle,
+    Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
+}
+
+impl Future for UrlLockFuture {
+    type Item = (Addon, AddonLock);
+    type Error = String;
+
+    fn poll(&mut self) -> Result<Async<(Addon, AddonLock)>, String> {
+        use self::LockInner::*;
+
+        loop {
+            let next = match self.inner {
+                Idle => Downloading(fetch(&self.client, &self.addon)?),
+                Downloading(ref mut f) => {
+                    let body = try_ready!(f.poll());
+                    let filepath = write_download(&self.addon, &body)?;
+                    let content_hash = hash::hash_file(&filepath)
+                        .map_err(|err| format!("{}", err))?;
+
+                    let timestamp = match self.old_lock {
+                        Some(ref old) if old.hash.as_ref() == Some(&content_hash) => old.timestamp,
+                        _ => now(),
+                    };
+
+                    let url = self.addon.url.clone().unwrap_or(String::new());
+                    let result = AddonLock {
+                        name: format!("url/{}", self.addon.name),
+                        resolved: url.clone(),
+                        version: String::from(&content_hash[..8]),
+                        timestamp,
+                        url: Some(url),
+                        hash: Some(content_hash),
+                        ..Default::default()
+                    };
+
+                    return Ok(Async::Ready((self.addon.clone(), result)));
+                },
+            };
+
+            self.inner = next;
+        }
+    }
+}
+
+struct UrlDownloadFuture {
+    inner: DownloadInner,
+    client: Client,
+    addon: Addon,
+    lock: AddonLock,
+}
+
+enum DownloadInner {
+    Idle,
+    Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
+}
+
+impl Future for UrlDownloadFuture {
+    type Item = (PathBuf, AddonLock);
+    type Error = String;
+
+    fn poll(&mut self) -> Result<Async<(PathBuf, AddonLock)>, String> {
+        use self::DownloadInner::*;
+
+        loop {
+            let next = match self.inner {
+                Idle => {
+                    let filepath = download_path(&self.addon);
+                    let cached_hash = hash::hash_file(&filepath).ok();
+
+                    if cached_hash.is_some() && cached_hash == self.lock.hash {
+                        return Ok(Async::Ready((filepath, self.lock.clone())));
+                    }
+
+                    Downloading(fetch(&self.client, &self.addon)?)
+                },
+                Downloading(ref mut f) => {
+                    let body = try_ready!(f.poll());
+                    let filepath = write_download(&self.addon, &body)?;
+
+                    let content_hash = hash::hash_file(&filepath)
+                        .map_err(|err| format!("{}", err))?;
+                    if self.lock.hash.is_some() && self.lock.hash != Some(content_hash) {
+                        return Err(format!("{} has changed since it was locked", self.lock.name));
+                    }
+
+                    return Ok(Async::Ready((filepath, self.lock.clone())));
+                },
+            };
+
+            self.inner = next;
+        }
+    }
+}
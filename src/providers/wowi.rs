@@ -0,0 +1,211 @@
+use ::{Addon, AddonLock, Flavor};
+use super::{Provider, ProviderMetadata};
+use super::{LockFuture, DownloadFuture};
+use ::futures::{Future, Async, Stream};
+use ::std::env;
+use ::std::path::{Path, PathBuf};
+use ::std::fs::File;
+use ::std::io::Write;
+
+use ::reqwest::async::{Client, Chunk};
+
+const DEFAULT_API_URL: &'static str = "https://api.mmoui.com/v3/game/WOW";
+const API_URL_ENV: &'static str = "WAM_WOWI_API_URL";
+
+const FILE_DETAILS_URL_TEMPLATE: &'static str = "{api}/filedetails/{}.json";
+
+/// Provider for wowinterface.com. Addons are referred to by the numeric
+/// id from their page url, as in `wowi/<id>`.
+pub struct WowiProvider {
+    api_url: String,
+}
+
+impl WowiProvider {
+    pub fn new() -> WowiProvider {
+        let api_url = env::var(API_URL_ENV)
+            .unwrap_or(String::from(DEFAULT_API_URL));
+
+        WowiProvider::with_api_url(&api_url)
+    }
+
+    pub fn with_api_url(api_url: &str) -> WowiProvider {
+        WowiProvider {
+            api_url: String::from(api_url.trim_end_matches('/')),
+        }
+    }
+}
+
+impl Default for WowiProvider {
+    fn default() -> WowiProvider {
+        WowiProvider::new()
+    }
+}
+
+impl Provider for WowiProvider {
+    fn metadata(&self) -> ProviderMetadata {
+        ProviderMetadata {
+            title: String::from("wowinterface"),
+            homepage: String::from("https://www.wowinterface.com"),
+        }
+    }
+
+    fn get_lock(&self, addon: Addon, _old_lock: Option<AddonLock>) -> LockFuture {
+        let url = FILE_DETAILS_URL_TEMPLATE
+            .replace("{api}", &self.api_url)
+            .replace("{}", &addon.name);
+
+        Box::new(WowiLockFuture {
+            inner: LockInner::Idle,
+            client: Client::new(),
+            addon, url,
+        })
+    }
+
+    fn download_addon(&self, _addon: Addon, lock: AddonLock) -> DownloadFuture {
+        Box::new(WowiDownloadFuture {
+            inner: DownloadInner::Idle,
+            client: Client::new(),
+            lock,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct FileDetails {
+    #[serde(rename = "UID")]
+    id: String,
+    #[serde(rename = "UIVersion")]
+    version: String,
+    // milliseconds since the epoch
+    #[serde(rename = "UIDate")]
+    date: u64,
+    #[serde(rename = "UIDownload")]
+    download: String,
+    #[serde(rename = "UIFileName", default)]
+    file_name: String,
+}
+
+struct WowiLockFuture {
+    inner: LockInner,
+    client: Client,
+    addon: Addon,
+    url: String,
+}
+
+enum LockInner {
+    Idle,
+    Resolving(Box<Future<Item = Vec<FileDetails>, Error = String> + Send>),
+}
+
+impl Future for WowiLockFuture {
+    type Item = (Addon, AddonLock);
+    type Error = String;
+
+    fn poll(&mut self) -> Result<Async<(Addon, AddonLock)>, String> {
+        use self::LockInner::*;
+
+        loop {
+            let next = match self.inner {
+                Idle => {
+                    let pending = self.client.get(&self.url).send()
+                        .and_then(|res| res.error_for_status())
+                        .and_then(|mut res| res.json::<Vec<FileDetails>>())
+                        .map_err(|err| format!("{}", err));
+
+                    Resolving(Box::new(pending))
+                },
+                Resolving(ref mut f) => {
+                    let mut details = try_ready!(f.poll());
+                    if details.is_empty() {
+                        return Err(format!("no file found for wowi/{}", self.addon.name));
+                    }
+
+                    let file = details.remove(0);
+
+                    // wowinterface lists builds for other flavors as separate
+                    // addons, so all we can do is make sure this isn't one
+                    let flavor = self.addon.flavor.unwrap_or_default().base();
+                    let file_flavor = Flavor::from_file_name(&file.file_name).unwrap_or(Flavor::Retail);
+                    if file_flavor != flavor {
+                        return Err(format!(
+                            "wowi/{} is built for {}, not {}",
+                            self.addon.name, file_flavor.name(), flavor.name()
+                        ));
+                    }
+
+                    if let Some(ref version) = self.addon.version {
+                        if version != &file.version {
+                            return Err(format!(
+                                "wowi/{} {} is not available, only the latest version ({}) can be installed",
+                                self.addon.name, version, file.version
+                            ));
+                        }
+                    }
+                    let result = AddonLock {
+                        name: format!("wowi/{}", self.addon.name),
+                        resolved: file.id,
+                        version: file.version,
+                        timestamp: file.date / 1000,
+                        url: Some(file.download),
+                        ..Default::default()
+                    };
+
+                    return Ok(Async::Ready((self.addon.clone(), result)));
+                },
+            };
+
+            self.inner = next;
+        }
+    }
+}
+
+struct WowiDownloadFuture {
+    inner: DownloadInner,
+    client: Client,
+    lock: AddonLock,
+}
+
+enum DownloadInner {
+    Idle,
+    Downloading(Box<Future<Item = Chunk, Error = String> + Send>),
+}
+
+impl Future for WowiDownloadFuture {
+    type Item = (PathBuf, AddonLock);
+    type Error = String;
+
+    fn poll(&mut self) -> Result<Async<(PathBuf, AddonLock)>, String> {
+        use self::DownloadInner::*;
+
+        loop {
+            let next = match self.inner {
+                Idle => {
+                    let url = self.lock.url.clone()
+                        .ok_or(format!("no download url in lock for {}", self.lock.name))?;
+
+                    let pending = self.client.get(&url).send()
+                        .and_then(|res| res.error_for_status())
+                        .and_then(|res| res.into_body().concat2())
+                        .map_err(|err| format!("{}", err));
+
+                    Downloading(Box::new(pending))
+                },
+                Downloading(ref mut f) => {
+                    let body = try_ready!(f.poll());
+
+                    // download links point to a php script, so the filename is
+                    // built from the file id instead
+                    let filename = format!("wowi-{}.zip", self.lock.resolved);
+                    let filepath = Path::new(".wam-temp").join(&filename);
+                    File::create(&filepath)
+                        .and_then(|mut file| file.write_all(&body))
+                        .map_err(|err| format!("could not write {}: {}", filepath.display(), err))?;
+
+                    return Ok(Async::Ready((filepath, self.lock.clone())));
+                },
+            };
+
+            self.inner = next;
+        }
+    }
+}
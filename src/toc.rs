@@ -0,0 +1,341 @@
+use ::Flavor;
+use ::std::fs::{self, File};
+use ::std::io::Read;
+use ::std::path::{Path, PathBuf};
+
+/// Metadata from an addon's `.toc` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toc {
+    pub title: Option<String>,
+    // newer tocs can list several interface versions, one per client they
+    // support, e.g. `## Interface: 110000, 40400`
+    pub interface: Vec<u32>,
+    pub version: Option<String>,
+    pub author: Option<String>,
+    // addon folders that have to be loaded before this one
+    pub dependencies: Vec<String>,
+    pub optional_dependencies: Vec<String>,
+    pub saved_variables: Vec<String>,
+    pub saved_variables_per_character: Vec<String>,
+    // ids of the addon on the sites it's published on, set by their packagers
+    pub curse_project_id: Option<String>,
+    pub wowi_id: Option<String>,
+    pub tukui_project_id: Option<String>,
+}
+
+impl Toc {
+    /// Parses the `## Key: Value` metadata lines of a toc file. Everything
+    /// else, like the list of files to load, is ignored.
+    pub fn parse(contents: &str) -> Toc {
+        let mut toc = Toc::default();
+
+        for line in contents.lines() {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            if !line.starts_with("##") {
+                continue;
+            }
+
+            let mut parts = line[2..].splitn(2, ":");
+            let key = parts.next().unwrap_or("").trim().to_lowercase();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                _ => continue,
+            };
+
+            match key.as_str() {
+                "title" => toc.title = Some(strip_colors(value)),
+                "interface" => toc.interface = split_list(value).iter()
+                    .filter_map(|it| it.parse().ok())
+                    .collect(),
+                "version" => toc.version = Some(String::from(value)),
+                "author" => toc.author = Some(strip_colors(value)),
+                // `Dep` followed by anything is an alias for `Dependencies`
+                "optionaldeps" => toc.optional_dependencies.extend(split_list(value)),
+                "requireddeps" => toc.dependencies.extend(split_list(value)),
+                key if key.starts_with("dep") => toc.dependencies.extend(split_list(value)),
+                "savedvariables" => toc.saved_variables.extend(split_list(value)),
+                "savedvariablespercharacter" => {
+                    toc.saved_variables_per_character.extend(split_list(value))
+                },
+                "x-curse-project-id" => toc.curse_project_id = Some(String::from(value)),
+                "x-wowi-id" => toc.wowi_id = Some(String::from(value)),
+                "x-tukui-projectid" => toc.tukui_project_id = Some(String::from(value)),
+                _ => (),
+            }
+        }
+
+        toc
+    }
+
+    /// The highest interface version the addon supports, which is the one
+    /// of the newest client it was updated for.
+    pub fn max_interface(&self) -> Option<u32> {
+        self.interface.iter().max().map(|it| *it)
+    }
+
+    /// Checks whether the client with interface version `current` flags the
+    /// addon as out of date. Of the interface versions in the toc, the one
+    /// for the same expansion as the client is compared if there is one.
+    pub fn is_outdated_for(&self, current: u32) -> bool {
+        let same_expansion = self.interface.iter()
+            .filter(|it| **it / 10000 == current / 10000)
+            .max()
+            .map(|it| *it);
+
+        match same_expansion.or(self.max_interface()) {
+            Some(interface) => interface < current,
+            _ => false,
+        }
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value.split(",")
+        .map(|it| it.trim())
+        .filter(|it| !it.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Suffixes of the tocs that the client loads instead of the plain one,
+/// most specific first. `Classic` is used by every classic client.
+fn toc_suffixes(flavor: Flavor) -> &'static [&'static str] {
+    match flavor.base() {
+        Flavor::ClassicEra => &["vanilla", "classic"],
+        Flavor::Classic => &["cata", "wrath", "wotlkc", "tbc", "bcc", "classic"],
+        _ => &["mainline"],
+    }
+}
+
+/// Finds the toc that the `flavor` client loads for an addon folder. Tocs
+/// are named after their folder, optionally with a flavor suffix like in
+/// `MyAddon_Classic.toc` or `MyAddon-Mainline.toc`.
+pub fn find(folder: &Path, flavor: Flavor) -> Option<PathBuf> {
+    let name = folder.file_name()?.to_string_lossy().to_lowercase();
+
+    // the client matches toc names case insensitively
+    let files = fs::read_dir(folder).ok()?
+        .filter_map(|it| it.ok())
+        .map(|it| it.path())
+        .filter(|it| it.is_file())
+        .collect::<Vec<PathBuf>>();
+
+    let find_file = |file_name: &str| files.iter()
+        .find(|it| it.file_name()
+            .map(|it| it.to_string_lossy().to_lowercase() == file_name)
+            .unwrap_or(false))
+        .map(Clone::clone);
+
+    toc_suffixes(flavor).iter()
+        .flat_map(|suffix| vec![
+            format!("{}_{}.toc", name, suffix),
+            format!("{}-{}.toc", name, suffix),
+        ])
+        .filter_map(|it| find_file(&it))
+        .next()
+        .or_else(|| find_file(&format!("{}.toc", name)))
+}
+
+/// The name of the folder that a toc belongs in, which is the toc's name
+/// without a flavor suffix, e.g. `MyAddon` for `MyAddon_Classic.toc`.
+pub fn folder_name(toc: &Path) -> Option<String> {
+    let name = toc.file_name()?.to_string_lossy().into_owned();
+    if !name.to_lowercase().ends_with(".toc") {
+        return None;
+    }
+
+    let stem = &name[..name.len() - 4];
+    let lower = stem.to_lowercase();
+    let suffix = [Flavor::Retail, Flavor::Classic, Flavor::ClassicEra].iter()
+        .flat_map(|flavor| toc_suffixes(*flavor).iter())
+        .find(|suffix| lower.ends_with(&format!("_{}", suffix)) || lower.ends_with(&format!("-{}", suffix)));
+
+    match suffix {
+        Some(suffix) => Some(String::from(&stem[..stem.len() - suffix.len() - 1])),
+        _ => Some(String::from(stem)),
+    }
+}
+
+/// Reads the toc that the `flavor` client loads for an installed addon folder.
+pub fn read(folder: &Path, flavor: Flavor) -> Option<Toc> {
+    let path = find(folder, flavor)?;
+
+    // some tocs aren't valid utf-8, usually because of titles in other
+    // languages, so they're read lossily
+    let mut contents = Vec::new();
+    File::open(path).ok()?
+        .read_to_end(&mut contents).ok()?;
+
+    Some(Toc::parse(&String::from_utf8_lossy(&contents)))
+}
+
+/// Converts a client version like `11.0.2.56196` to the interface version
+/// tocs use for it, `110002`.
+pub fn interface_from_version(version: &str) -> Option<u32> {
+    let parts = version.split(".")
+        .take(3)
+        .map(|it| it.parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()?;
+
+    if parts.len() != 3 {
+        return None;
+    }
+
+    Some(parts[0] * 10000 + parts[1] * 100 + parts[2])
+}
+
+/// Removes the `|cAARRGGBB` and `|r` escapes that addons use to color
+/// their titles in the addon list.
+fn strip_colors(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '|' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.peek() {
+            Some(&'c') | Some(&'C') => {
+                chars.nth(8);
+            },
+            Some(&'r') | Some(&'R') => {
+                chars.next();
+            },
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::std::env;
+    use ::std::fs;
+
+    const FULL_TOC: &'static str = "\u{feff}## Interface: 110002, 40400, 11503
+## Title: |cff00ff00My|r Addon
+## Version: 1.2.3
+## Author: |cffff0000Someone|r
+## Dependencies: LibStub, Ace3
+## RequiredDeps: LibBase
+## DepA: LibOther
+## OptionalDeps: LibSharedMedia-3.0, Masque
+## SavedVariables: MyAddonDB
+## SavedVariablesPerCharacter: MyAddonCharDB, MyAddonHistory
+## X-Curse-Project-ID: 12345
+## X-WoWI-ID: 678
+## X-Tukui-ProjectID: 9
+# a comment
+MyAddon.lua
+";
+
+    #[test]
+    fn parses_every_key() {
+        let toc = Toc::parse(FULL_TOC);
+
+        assert_eq!(toc.interface, vec![110002, 40400, 11503]);
+        assert_eq!(toc.title, Some(String::from("My Addon")));
+        assert_eq!(toc.version, Some(String::from("1.2.3")));
+        assert_eq!(toc.author, Some(String::from("Someone")));
+        assert_eq!(toc.dependencies, vec!["LibStub", "Ace3", "LibBase", "LibOther"]);
+        assert_eq!(toc.optional_dependencies, vec!["LibSharedMedia-3.0", "Masque"]);
+        assert_eq!(toc.saved_variables, vec!["MyAddonDB"]);
+        assert_eq!(toc.saved_variables_per_character, vec!["MyAddonCharDB", "MyAddonHistory"]);
+        assert_eq!(toc.curse_project_id, Some(String::from("12345")));
+        assert_eq!(toc.wowi_id, Some(String::from("678")));
+        assert_eq!(toc.tukui_project_id, Some(String::from("9")));
+        assert_eq!(toc.max_interface(), Some(110002));
+    }
+
+    #[test]
+    fn strips_leading_bom() {
+        let toc = Toc::parse("\u{feff}## Interface: 11503\n## Title: Bom");
+
+        assert_eq!(toc.interface, vec![11503]);
+        assert_eq!(toc.title, Some(String::from("Bom")));
+    }
+
+    #[test]
+    fn checks_interface_of_same_expansion() {
+        let toc = Toc::parse("## Interface: 110000, 40400");
+
+        assert!(toc.is_outdated_for(110002));
+        assert!(!toc.is_outdated_for(110000));
+        assert!(!toc.is_outdated_for(40400));
+        assert!(toc.is_outdated_for(40401));
+        assert!(!Toc::parse("## Title: No Interface").is_outdated_for(110002));
+    }
+
+    #[test]
+    fn converts_client_versions() {
+        assert_eq!(interface_from_version("11.0.2.56196"), Some(110002));
+        assert_eq!(interface_from_version("1.15.3"), Some(11503));
+        assert_eq!(interface_from_version("4.4.0.56489"), Some(40400));
+        assert_eq!(interface_from_version("11.0"), None);
+        assert_eq!(interface_from_version("beta"), None);
+    }
+
+    fn addon_folder(name: &str, tocs: &[&str]) -> PathBuf {
+        let folder = env::temp_dir()
+            .join(format!("wam-toc-test-{}-{}", ::std::process::id(), name))
+            .join("MyAddon");
+
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        for toc in tocs {
+            fs::write(folder.join(toc), "## Interface: 110002").unwrap();
+        }
+
+        folder
+    }
+
+    fn found(folder: &Path, flavor: Flavor) -> String {
+        find(folder, flavor).unwrap().file_name().unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn finds_flavor_specific_tocs() {
+        let folder = addon_folder("flavors", &["MyAddon.toc", "MyAddon_Classic.toc", "MyAddon-Mainline.toc"]);
+
+        assert_eq!(found(&folder, Flavor::Retail), "MyAddon-Mainline.toc");
+        assert_eq!(found(&folder, Flavor::Ptr), "MyAddon-Mainline.toc");
+        assert_eq!(found(&folder, Flavor::Classic), "MyAddon_Classic.toc");
+        assert_eq!(found(&folder, Flavor::ClassicEra), "MyAddon_Classic.toc");
+
+        fs::remove_dir_all(folder.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn prefers_most_specific_suffix() {
+        let folder = addon_folder("specific", &["MyAddon.toc", "MyAddon_Classic.toc", "MyAddon_Vanilla.toc"]);
+
+        assert_eq!(found(&folder, Flavor::ClassicEra), "MyAddon_Vanilla.toc");
+        assert_eq!(found(&folder, Flavor::Classic), "MyAddon_Classic.toc");
+        assert_eq!(found(&folder, Flavor::Retail), "MyAddon.toc");
+
+        fs::remove_dir_all(folder.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn strips_flavor_suffix_from_folder_name() {
+        assert_eq!(folder_name(Path::new("MyAddon.toc")), Some(String::from("MyAddon")));
+        assert_eq!(folder_name(Path::new("src/MyAddon_Classic.toc")), Some(String::from("MyAddon")));
+        assert_eq!(folder_name(Path::new("MyAddon-Mainline.TOC")), Some(String::from("MyAddon")));
+        assert_eq!(folder_name(Path::new("My_Addon.toc")), Some(String::from("My_Addon")));
+        assert_eq!(folder_name(Path::new("MyAddon.lua")), None);
+    }
+
+    #[test]
+    fn falls_back_to_plain_toc() {
+        let folder = addon_folder("plain", &["myaddon.toc"]);
+
+        assert_eq!(found(&folder, Flavor::Classic), "myaddon.toc");
+        assert!(read(&folder, Flavor::Retail).is_some());
+
+        fs::remove_dir_all(folder.parent().unwrap()).unwrap();
+    }
+}